@@ -0,0 +1,396 @@
+//! A typed Markdown tree, modelled on mdast.
+//!
+//! `MarkdownConverter::convert_to_ast` lowers HTML into a `Document`, which
+//! can be inspected or rewritten before `MarkdownConverter::serialize` writes
+//! it out. Text in the tree is literal: escaping, list numbering and line
+//! prefixes are all left to the serializer.
+
+/// Column alignment of a table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Alignment {
+    #[default]
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// A whole Markdown document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Document {
+    pub children: Vec<Block>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    Paragraph(Vec<Inline>),
+    /// `# Title {#id .class key=value}`, with the identifier only if it has
+    /// to be written out.
+    Heading {
+        level: u8,
+        id: Option<String>,
+        classes: Vec<String>,
+        attributes: Vec<(String, String)>,
+        children: Vec<Inline>,
+    },
+    ThematicBreak,
+    BlockQuote(Vec<Block>),
+    /// `> [!TYPE]± Title`, with the type as it is written. A callout with
+    /// the default title has no title.
+    Callout {
+        kind: String,
+        fold: Option<char>,
+        title: Vec<Inline>,
+        children: Vec<Block>,
+    },
+    /// A list numbered from `start` if it is ordered.
+    List {
+        ordered: bool,
+        start: u64,
+        items: Vec<ListItem>,
+    },
+    CodeBlock {
+        info: String,
+        code: String,
+    },
+    /// `$$tex$$` on a line of its own.
+    Math(String),
+    /// A pipe table, whose first row is the header.
+    Table(Vec<Vec<TableCell>>),
+    DefinitionList(Vec<DefinitionItem>),
+    FootnoteDefinition {
+        label: String,
+        children: Vec<Block>,
+    },
+    /// Raw HTML, kept because of `UnknownElementPolicy`.
+    Html(String),
+    /// Markdown written by a handler that doesn't lower its element.
+    Markdown(String),
+    /// The blocks lowered from the element at `path`, written as they are.
+    /// Only made by `MarkdownConverter::convert_with_source_map`, to find
+    /// where the element was written. As the only block of a list item or
+    /// definition it stands for the item or definition itself.
+    Source {
+        path: Vec<usize>,
+        children: Vec<Block>,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ListItem {
+    /// `Some` for task list items, with whether the task is done.
+    pub checked: Option<bool>,
+    pub children: Vec<Block>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TableCell {
+    pub alignment: Alignment,
+    pub children: Vec<Inline>,
+}
+
+/// A group of terms followed by their definitions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DefinitionItem {
+    pub terms: Vec<Vec<Inline>>,
+    pub definitions: Vec<Vec<Block>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Inline {
+    Text(String),
+    Emphasis(Vec<Inline>),
+    Strong(Vec<Inline>),
+    Strikethrough(Vec<Inline>),
+    Highlight(Vec<Inline>),
+    Underline(Vec<Inline>),
+    Superscript(Vec<Inline>),
+    Subscript(Vec<Inline>),
+    Keyboard(Vec<Inline>),
+    Abbreviation {
+        title: Option<String>,
+        children: Vec<Inline>,
+    },
+    Code(String),
+    Math {
+        tex: String,
+        display: bool,
+    },
+    /// A link, written in reference style when it has a `reference` label.
+    Link {
+        url: String,
+        title: Option<String>,
+        reference: Option<String>,
+        children: Vec<Inline>,
+    },
+    /// An image. Sizes are kept as written in the HTML, e.g. `300px`.
+    Image {
+        url: String,
+        alt: String,
+        title: Option<String>,
+        width: Option<String>,
+        height: Option<String>,
+    },
+    WikiLink {
+        target: String,
+        alias: Option<String>,
+    },
+    FootnoteReference(String),
+    /// A hard line break.
+    Break,
+    /// Raw HTML, kept because of `UnknownElementPolicy`.
+    Html(String),
+    /// Markdown written by a handler that doesn't lower its element.
+    Markdown(String),
+    /// The content of the table cell or definition term at `path`, as the
+    /// only inline content of it. Like `Block::Source`, only made for a
+    /// source map.
+    Source {
+        path: Vec<usize>,
+        children: Vec<Inline>,
+    },
+}
+
+/// What an element lowers to, as the same element can be a block in one place
+/// and inline content in another.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Node {
+    Block(Block),
+    Inline(Inline),
+}
+
+impl Node {
+    /// Wraps the output of `ElementHandler::handle`. Block handlers start
+    /// their output on a new line, which is what tells the two apart.
+    pub fn markdown(markdown: String) -> Option<Node> {
+        if markdown.trim().is_empty() {
+            None
+        } else if markdown.starts_with('\n') {
+            Some(Node::Block(Block::Markdown(markdown.trim().to_string())))
+        } else {
+            Some(Node::Inline(Inline::Markdown(markdown)))
+        }
+    }
+}
+
+/// Turns the nodes lowered from the children of a block element into blocks,
+/// with each run of inline content becoming a paragraph.
+pub fn into_blocks(nodes: Vec<Node>) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut inlines = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Inline(inline) => inlines.push(inline),
+            Node::Block(block) => {
+                push_paragraph(&mut blocks, std::mem::take(&mut inlines));
+                blocks.push(block);
+            }
+        }
+    }
+    push_paragraph(&mut blocks, inlines);
+    blocks
+}
+
+/// Turns the nodes lowered from the children of an inline element into inline
+/// content. Blocks found there are flattened, with a space between them.
+pub fn into_inlines(nodes: Vec<Node>) -> Vec<Inline> {
+    let mut inlines = Vec::new();
+    for node in nodes {
+        match node {
+            Node::Inline(inline) => inlines.push(inline),
+            Node::Block(block) => {
+                if !inlines.is_empty() {
+                    inlines.push(Inline::Text(" ".to_string()));
+                }
+                block.flatten_into(&mut inlines);
+            }
+        }
+    }
+    inlines
+}
+
+fn push_paragraph(blocks: &mut Vec<Block>, inlines: Vec<Inline>) {
+    // Whitespace between blocks isn't content
+    let is_blank = inlines
+        .iter()
+        .all(|inline| matches!(inline, Inline::Text(text) if text.trim().is_empty()));
+    if !is_blank {
+        blocks.push(Block::Paragraph(inlines));
+    }
+}
+
+impl Block {
+    fn flatten_into(self, inlines: &mut Vec<Inline>) {
+        fn flatten_blocks(blocks: Vec<Block>, inlines: &mut Vec<Inline>) {
+            for (index, block) in blocks.into_iter().enumerate() {
+                if index > 0 {
+                    inlines.push(Inline::Text(" ".to_string()));
+                }
+                block.flatten_into(inlines);
+            }
+        }
+
+        match self {
+            Block::Paragraph(children) | Block::Heading { children, .. } => {
+                inlines.extend(children)
+            }
+            Block::ThematicBreak => {}
+            Block::BlockQuote(children)
+            | Block::FootnoteDefinition { children, .. }
+            | Block::Source { children, .. } => flatten_blocks(children, inlines),
+            Block::Callout {
+                title, children, ..
+            } => {
+                inlines.extend(title);
+                flatten_blocks(children, inlines);
+            }
+            Block::List { items, .. } => {
+                let blocks = items.into_iter().flat_map(|item| item.children).collect();
+                flatten_blocks(blocks, inlines);
+            }
+            Block::CodeBlock { code, .. } => inlines.push(Inline::Code(code)),
+            Block::Math(tex) => inlines.push(Inline::Math { tex, display: true }),
+            Block::Table(rows) => {
+                let cells = rows.into_iter().flatten().map(|cell| cell.children);
+                for (index, children) in cells.enumerate() {
+                    if index > 0 {
+                        inlines.push(Inline::Text(" ".to_string()));
+                    }
+                    inlines.extend(children);
+                }
+            }
+            Block::DefinitionList(items) => {
+                for item in items {
+                    for term in item.terms {
+                        inlines.extend(term);
+                        inlines.push(Inline::Text(" ".to_string()));
+                    }
+                    flatten_blocks(item.definitions.into_iter().flatten().collect(), inlines);
+                }
+            }
+            Block::Html(html) => inlines.push(Inline::Html(html)),
+            Block::Markdown(markdown) => inlines.push(Inline::Markdown(markdown)),
+        }
+    }
+
+    /// The blocks directly inside this one.
+    pub fn children_mut(&mut self) -> Vec<&mut Block> {
+        match self {
+            Block::BlockQuote(children)
+            | Block::Callout { children, .. }
+            | Block::FootnoteDefinition { children, .. }
+            | Block::Source { children, .. } => children.iter_mut().collect(),
+            Block::List { items, .. } => items
+                .iter_mut()
+                .flat_map(|item| item.children.iter_mut())
+                .collect(),
+            Block::DefinitionList(items) => items
+                .iter_mut()
+                .flat_map(|item| item.definitions.iter_mut().flatten())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The inline content directly inside this block.
+    pub fn inlines_mut(&mut self) -> Vec<&mut Vec<Inline>> {
+        match self {
+            Block::Paragraph(children) | Block::Heading { children, .. } => vec![children],
+            Block::Callout { title, .. } => vec![title],
+            Block::Table(rows) => rows
+                .iter_mut()
+                .flatten()
+                .map(|cell| &mut cell.children)
+                .collect(),
+            Block::DefinitionList(items) => {
+                items.iter_mut().flat_map(|item| &mut item.terms).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Inline {
+    /// The inline content inside this one, for the elements that have any.
+    pub fn children_mut(&mut self) -> Option<&mut Vec<Inline>> {
+        match self {
+            Inline::Emphasis(children)
+            | Inline::Strong(children)
+            | Inline::Strikethrough(children)
+            | Inline::Highlight(children)
+            | Inline::Underline(children)
+            | Inline::Superscript(children)
+            | Inline::Subscript(children)
+            | Inline::Keyboard(children)
+            | Inline::Abbreviation { children, .. }
+            | Inline::Link { children, .. }
+            | Inline::Source { children, .. } => Some(children),
+            _ => None,
+        }
+    }
+
+    /// The text a reader sees, without any markup.
+    pub fn plain_text(inlines: &[Inline]) -> String {
+        let mut text = String::new();
+        for inline in inlines {
+            match inline {
+                Inline::Text(content) | Inline::Code(content) => text.push_str(content),
+                Inline::Math { tex, .. } => text.push_str(tex),
+                Inline::Image { alt, .. } => text.push_str(alt),
+                Inline::WikiLink { target, alias } => {
+                    text.push_str(alias.as_deref().unwrap_or(target))
+                }
+                Inline::Break => text.push('\n'),
+                Inline::FootnoteReference(_) | Inline::Html(_) | Inline::Markdown(_) => {}
+                Inline::Emphasis(children)
+                | Inline::Strong(children)
+                | Inline::Strikethrough(children)
+                | Inline::Highlight(children)
+                | Inline::Underline(children)
+                | Inline::Superscript(children)
+                | Inline::Subscript(children)
+                | Inline::Keyboard(children)
+                | Inline::Abbreviation { children, .. }
+                | Inline::Link { children, .. }
+                | Inline::Source { children, .. } => text.push_str(&Inline::plain_text(children)),
+            }
+        }
+        text
+    }
+}
+
+impl Document {
+    /// Calls `visit` on every block of the document, parents before their
+    /// children, e.g. to shift the level of all headings.
+    pub fn visit_blocks_mut(&mut self, visit: &mut impl FnMut(&mut Block)) {
+        fn walk(block: &mut Block, visit: &mut impl FnMut(&mut Block)) {
+            visit(block);
+            for child in block.children_mut() {
+                walk(child, visit);
+            }
+        }
+
+        for block in &mut self.children {
+            walk(block, visit);
+        }
+    }
+
+    /// Calls `visit` on every inline element of the document, parents before
+    /// their children, e.g. to rewrite the URLs of links.
+    pub fn visit_inlines_mut(&mut self, visit: &mut impl FnMut(&mut Inline)) {
+        fn walk(inlines: &mut [Inline], visit: &mut impl FnMut(&mut Inline)) {
+            for inline in inlines {
+                visit(inline);
+                if let Some(children) = inline.children_mut() {
+                    walk(children, visit);
+                }
+            }
+        }
+
+        self.visit_blocks_mut(&mut |block| {
+            for inlines in block.inlines_mut() {
+                walk(inlines, visit);
+            }
+        });
+    }
+}
@@ -0,0 +1,389 @@
+//! Command-line access to the conversions of the editor, for scripts and CI
+//! checks that need to read and write the same Markdown as Rhyolite.
+//!
+//! ```text
+//! rhyolite-md html2md [OPTIONS] [FILE]     HTML to Markdown
+//! rhyolite-md md2html [OPTIONS] [FILE]     Markdown to HTML
+//! rhyolite-md fmt [OPTIONS] [FILE]...      Markdown as the editor saves it
+//! ```
+//!
+//! Input is read from the files given, or from standard input if there are
+//! none or one is `-`, and written to standard output. Warnings go to
+//! standard error. The Markdown style defaults to the one the editor saves
+//! notes in.
+
+use markdown_engine::{
+    join_frontmatter, split_frontmatter, ConverterOptions, HeadingStyle, HtmlRenderer,
+    LineBreakStyle, LinkStyle, MarkdownConverter, ScriptStyle, UnderlineStyle,
+    UnknownElementPolicy,
+};
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: rhyolite-md <COMMAND> [OPTIONS] [FILE]...
+
+Commands:
+  html2md    Convert HTML to Markdown
+  md2html    Render Markdown to HTML, leaving out the frontmatter
+  fmt        Write Markdown the way the editor saves it, by rendering it and
+             converting it back. Frontmatter and whether the file ends with
+             a newline are kept.
+
+Files are read from standard input if none are given or one is `-`.
+
+Options:
+  -o, --output <FILE>          Write to FILE instead of standard output
+      --check                  fmt: don't write anything, list the files that
+                               would change and exit with 1 if there are any
+      --source-positions       md2html: add data-sourcepos attributes, with
+                               the lines of the file, frontmatter included
+
+Markdown style (html2md, fmt):
+      --bullet <-|*|+>
+      --emphasis <*|_>
+      --strong <*|_>
+      --heading-style <atx|setext>
+      --fence <`|~>
+      --fence-length <N>
+      --underline <html|plus|strip>
+      --scripts <html|caret>
+      --line-break <backslash|spaces>
+      --link-style <inline|reference>
+      --unknown-elements <embedded|strip|keep>
+      --wrap <N>                   Wrap paragraphs at column N
+      --trailing-newline           Always end with a newline
+      --max-depth <N>
+  -h, --help
+";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Command {
+    HtmlToMarkdown,
+    MarkdownToHtml,
+    Format,
+}
+
+struct Args {
+    command: Command,
+    files: Vec<String>,
+    output: Option<String>,
+    check: bool,
+    source_positions: bool,
+    options: ConverterOptions,
+}
+
+fn main() -> ExitCode {
+    let args = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            print!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("rhyolite-md: {}\n\n{}", message, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(message) => {
+            eprintln!("rhyolite-md: {}", message);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Returns `None` if help was asked for.
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let command = match args.next().as_deref() {
+        Some("html2md") => Command::HtmlToMarkdown,
+        Some("md2html") => Command::MarkdownToHtml,
+        Some("fmt") => Command::Format,
+        Some("-h" | "--help") => return Ok(None),
+        Some(other) => return Err(format!("unknown command `{}`", other)),
+        None => return Err("no command given".to_string()),
+    };
+
+    let mut parsed = Args {
+        command,
+        files: Vec::new(),
+        output: None,
+        check: false,
+        source_positions: false,
+        options: ConverterOptions::editor(),
+    };
+    let options = &mut parsed.options;
+
+    while let Some(arg) = args.next() {
+        if arg == "-" || !arg.starts_with('-') {
+            parsed.files.push(arg);
+            continue;
+        }
+        if arg == "--" {
+            parsed.files.extend(args.by_ref());
+            break;
+        }
+
+        // Values are given as `--name value` or `--name=value`
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("`{}` needs a value", name))
+        };
+
+        match name.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => parsed.output = Some(value()?),
+            "--check" => parsed.check = true,
+            "--source-positions" => parsed.source_positions = true,
+            "--bullet" => options.bullet = choice(&name, &value()?, &['-', '*', '+'])?,
+            "--emphasis" => options.emphasis_delimiter = choice(&name, &value()?, &['*', '_'])?,
+            "--strong" => options.strong_delimiter = choice(&name, &value()?, &['*', '_'])?,
+            "--fence" => options.fence_char = choice(&name, &value()?, &['`', '~'])?,
+            "--fence-length" => options.fence_length = number(&name, &value()?)?,
+            "--wrap" => options.wrap_width = Some(number(&name, &value()?)?),
+            "--max-depth" => options.max_depth = number(&name, &value()?)?,
+            "--trailing-newline" => options.trailing_newline = true,
+            "--heading-style" => {
+                options.heading_style = match value()?.as_str() {
+                    "atx" => HeadingStyle::Atx,
+                    "setext" => HeadingStyle::Setext,
+                    other => return Err(invalid(&name, other)),
+                }
+            }
+            "--underline" => {
+                options.underline_style = match value()?.as_str() {
+                    "html" => UnderlineStyle::Html,
+                    "plus" => UnderlineStyle::Plus,
+                    "strip" => UnderlineStyle::Strip,
+                    other => return Err(invalid(&name, other)),
+                }
+            }
+            "--scripts" => {
+                options.script_style = match value()?.as_str() {
+                    "html" => ScriptStyle::Html,
+                    "caret" => ScriptStyle::Caret,
+                    other => return Err(invalid(&name, other)),
+                }
+            }
+            "--line-break" => {
+                options.line_break = match value()?.as_str() {
+                    "backslash" => LineBreakStyle::Backslash,
+                    "spaces" => LineBreakStyle::Spaces,
+                    other => return Err(invalid(&name, other)),
+                }
+            }
+            "--link-style" => {
+                options.link_style = match value()?.as_str() {
+                    "inline" => LinkStyle::Inline,
+                    "reference" => LinkStyle::Reference,
+                    other => return Err(invalid(&name, other)),
+                }
+            }
+            "--unknown-elements" => {
+                options.unknown_elements = match value()?.as_str() {
+                    "embedded" => UnknownElementPolicy::embedded(),
+                    "strip" => UnknownElementPolicy::Strip,
+                    "keep" => UnknownElementPolicy::KeepHtml,
+                    other => return Err(invalid(&name, other)),
+                }
+            }
+            _ => return Err(format!("unknown option `{}`", name)),
+        }
+    }
+
+    if parsed.check && parsed.command != Command::Format {
+        return Err("`--check` only works with fmt".to_string());
+    }
+    if parsed.check && parsed.output.is_some() {
+        return Err("`--check` doesn't write any output".to_string());
+    }
+    if parsed.files.len() > 1 && !parsed.check {
+        return Err("only one file can be converted at a time, except with `--check`".to_string());
+    }
+    if parsed.files.is_empty() {
+        parsed.files.push("-".to_string());
+    }
+    Ok(Some(parsed))
+}
+
+fn invalid(name: &str, value: &str) -> String {
+    format!("`{}` is not a valid value for `{}`", value, name)
+}
+
+fn choice(name: &str, value: &str, allowed: &[char]) -> Result<char, String> {
+    let mut chars = value.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if allowed.contains(&c) => Ok(c),
+        _ => Err(invalid(name, value)),
+    }
+}
+
+fn number(name: &str, value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| invalid(name, value))
+}
+
+/// Returns false if `--check` found files that aren't formatted.
+fn run(args: &Args) -> Result<bool, String> {
+    let converter = MarkdownConverter::with_options(args.options.clone());
+    let mut formatted = true;
+
+    for file in &args.files {
+        let input = read_input(file)?;
+        let output = match args.command {
+            Command::HtmlToMarkdown => html_to_markdown(&converter, &input, file)?,
+            Command::MarkdownToHtml => markdown_to_html(&input, args.source_positions),
+            Command::Format => format_markdown(&converter, &input, file)?,
+        };
+
+        if args.check {
+            if output != input {
+                println!("{}", file);
+                formatted = false;
+            }
+        } else {
+            write_output(args.output.as_deref(), &output)?;
+        }
+    }
+
+    Ok(formatted)
+}
+
+/// Converts like the editor does when it saves a note.
+fn html_to_markdown(
+    converter: &MarkdownConverter,
+    html: &str,
+    file: &str,
+) -> Result<String, String> {
+    let conversion = converter
+        .try_convert_with_warnings(html)
+        .map_err(|e| format!("{}: {}", file, e))?;
+    for warning in &conversion.warnings {
+        eprintln!("{}: warning: {}", file, warning);
+    }
+    Ok(conversion.markdown)
+}
+
+/// Renders a note like the editor does when it opens it, without its
+/// frontmatter.
+fn markdown_to_html(markdown: &str, source_positions: bool) -> String {
+    let body = split_frontmatter(markdown).map_or(markdown, |(_, body)| body);
+    // The body starts on the line after the frontmatter
+    let first_line = markdown[..markdown.len() - body.len()]
+        .matches('\n')
+        .count()
+        + 1;
+    HtmlRenderer::new()
+        .set_source_positions(source_positions)
+        .set_first_line(first_line)
+        .render(body)
+}
+
+/// Renders the Markdown and converts it back. Frontmatter is written back
+/// exactly as it was, as the editor does, and a final newline is kept.
+fn format_markdown(
+    converter: &MarkdownConverter,
+    markdown: &str,
+    file: &str,
+) -> Result<String, String> {
+    let mut formatted = match split_frontmatter(markdown) {
+        Some((frontmatter, body)) => {
+            let body = html_to_markdown(converter, &HtmlRenderer::new().render(body), file)?;
+            join_frontmatter(frontmatter, &body)
+        }
+        None => html_to_markdown(converter, &HtmlRenderer::new().render(markdown), file)?,
+    };
+    if markdown.ends_with('\n') && !formatted.ends_with('\n') {
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+fn read_input(file: &str) -> Result<String, String> {
+    let mut input = String::new();
+    let result = if file == "-" {
+        io::stdin().read_to_string(&mut input).map(|_| ())
+    } else {
+        std::fs::read_to_string(file).map(|content| input = content)
+    };
+    result.map_err(|e| format!("{}: {}", file, e))?;
+    Ok(input)
+}
+
+fn write_output(output: Option<&str>, content: &str) -> Result<(), String> {
+    match output {
+        Some(path) => std::fs::write(path, content).map_err(|e| format!("{}: {}", path, e)),
+        None => io::stdout()
+            .write_all(content.as_bytes())
+            .map_err(|e| format!("stdout: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Option<Args>, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    /// Writes a file to check into the temporary directory.
+    fn note(name: &str, content: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("rhyolite-md-{}-{}", std::process::id(), name));
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn defaults_to_the_editor_options() {
+        let args = args(&["fmt"]).unwrap().unwrap();
+        assert_eq!(
+            format!("{:?}", args.options),
+            format!("{:?}", ConverterOptions::editor())
+        );
+        assert_eq!(args.files, ["-"]);
+    }
+
+    #[test]
+    fn rejects_check_outside_of_fmt() {
+        assert!(args(&["md2html", "--check"]).is_err());
+        assert!(args(&["fmt", "--check", "-o", "out.md"]).is_err());
+        assert!(args(&["fmt", "a.md", "b.md"]).is_err());
+        assert!(args(&["fmt", "--check", "a.md", "b.md"]).is_ok());
+    }
+
+    #[test]
+    fn check_lists_only_files_that_would_change() {
+        let formatted = note("formatted.md", "---\ntags: [a]\n...\n\n# Note\n\n- Item\n");
+        let unformatted = note("unformatted.md", "# Note\n\n* Item\n");
+        assert_eq!(
+            run(&args(&["fmt", "--check", &formatted]).unwrap().unwrap()),
+            Ok(true)
+        );
+        assert_eq!(
+            run(&args(&["fmt", "--check", &formatted, &unformatted])
+                .unwrap()
+                .unwrap()),
+            Ok(false)
+        );
+        std::fs::remove_file(formatted).unwrap();
+        std::fs::remove_file(unformatted).unwrap();
+    }
+
+    #[test]
+    fn renders_notes_without_their_frontmatter() {
+        assert_eq!(
+            markdown_to_html("---\ntags: [a]\n---\n\n# Note\n", true),
+            "<h1 id=\"note\" data-sourcepos=\"5:1-5:6\">Note</h1>\n"
+        );
+    }
+}
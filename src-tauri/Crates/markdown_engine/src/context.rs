@@ -0,0 +1,355 @@
+//! State of a conversion that is passed down to the element handlers.
+
+use html5ever::LocalName;
+use markup5ever_rcdom::Handle;
+use std::rc::Rc;
+
+use crate::slug::Slugger;
+use crate::{ConvertError, ConvertWarning};
+
+/// Nesting state of a list that is currently being lowered.
+pub struct ListContext {
+    pub(crate) ordered: bool,
+    pub(crate) next_number: u64,
+}
+
+impl ListContext {
+    pub fn is_ordered(&self) -> bool {
+        self.ordered
+    }
+}
+
+/// A link reference definition, written as `[label]: url "title"` at the end
+/// of the document.
+pub struct LinkDefinition {
+    pub(crate) label: String,
+    pub(crate) url: String,
+    pub(crate) title: Option<String>,
+}
+
+impl LinkDefinition {
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+}
+
+/// An element that is being lowered.
+struct Element {
+    name: LocalName,
+    node: Handle,
+    // Where the element is below `<body>`, if a source map is being made
+    path: Option<Vec<usize>>,
+}
+
+/// Describes where in the document a handler is being called.
+///
+/// The converter keeps the element stack up to date as it walks the DOM, so
+/// handlers can look at their surroundings without going through the node's
+/// parent pointers.
+#[derive(Default)]
+pub struct ConversionContext {
+    ancestors: Vec<Element>,
+    lists: Vec<ListContext>,
+    link_definitions: Vec<LinkDefinition>,
+    headings: Slugger,
+    warnings: Vec<ConvertWarning>,
+    error: Option<ConvertError>,
+    // Whether the blocks of each element are kept together for a source map
+    source_map: bool,
+}
+
+impl ConversionContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a context for `MarkdownConverter::convert_with_source_map`.
+    pub(crate) fn with_source_map() -> Self {
+        Self {
+            source_map: true,
+            ..Self::default()
+        }
+    }
+
+    /// Tag names of the elements enclosing the current node, outermost first.
+    /// While a handler runs, its own element is the last entry.
+    pub fn ancestors(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.ancestors.iter().map(|element| element.name.as_ref())
+    }
+
+    /// Nodes that follow the handler's element within its parent, text
+    /// included.
+    pub fn next_siblings(&self) -> Vec<Handle> {
+        let [.., outer, element] = self.ancestors.as_slice() else {
+            return Vec::new();
+        };
+        // The outer element is the parent, unless its handler lowers the
+        // content of some of its descendants itself
+        let Some(lineage) = lineage(&outer.node, &element.node) else {
+            return Vec::new();
+        };
+        let parent = &lineage[lineage.len() - 2];
+        let children = parent.children.borrow();
+        children
+            .iter()
+            .skip_while(|child| !Rc::ptr_eq(child, &element.node))
+            .skip(1)
+            .cloned()
+            .collect()
+    }
+
+    /// Tag name of the element containing the handler's element.
+    pub fn parent(&self) -> Option<&str> {
+        self.ancestors().rev().nth(1)
+    }
+
+    /// Number of elements enclosing the current node.
+    pub fn depth(&self) -> usize {
+        self.ancestors.len()
+    }
+
+    /// Returns true if the current node is inside an element with the given tag.
+    pub fn is_within(&self, tag: &str) -> bool {
+        self.ancestors().any(|name| name == tag)
+    }
+
+    pub fn in_pre(&self) -> bool {
+        self.is_within("pre")
+    }
+
+    pub fn in_code(&self) -> bool {
+        self.is_within("code")
+    }
+
+    pub fn in_blockquote(&self) -> bool {
+        self.is_within("blockquote")
+    }
+
+    pub fn in_table(&self) -> bool {
+        self.is_within("table")
+    }
+
+    /// Lists currently being lowered, innermost last.
+    pub fn lists(&self) -> &[ListContext] {
+        &self.lists
+    }
+
+    /// Link reference definitions collected so far, in the order they were added.
+    pub fn link_definitions(&self) -> &[LinkDefinition] {
+        &self.link_definitions
+    }
+
+    /// Returns the label of a definition for `url` and `title`, adding one
+    /// with the next free number as its label if there is none yet.
+    pub fn link_reference(&mut self, url: &str, title: Option<&str>) -> String {
+        if let Some(existing) = self
+            .link_definitions
+            .iter()
+            .find(|definition| definition.url == url && definition.title.as_deref() == title)
+        {
+            return existing.label.clone();
+        }
+
+        let label = (1..)
+            .map(|number: usize| number.to_string())
+            .find(|label| self.find_definition(label).is_none())
+            .unwrap();
+        self.link_definitions.push(LinkDefinition {
+            label: label.clone(),
+            url: url.to_string(),
+            title: title.map(str::to_string),
+        });
+        label
+    }
+
+    /// Adds a definition under the given label. Returns false if the label is
+    /// already defined with a different destination, as only the first
+    /// definition of a label counts.
+    pub fn define_link(&mut self, label: &str, url: &str, title: Option<&str>) -> bool {
+        if let Some(existing) = self.find_definition(label) {
+            return existing.url == url && existing.title.as_deref() == title;
+        }
+
+        self.link_definitions.push(LinkDefinition {
+            label: label.to_string(),
+            url: url.to_string(),
+            title: title.map(str::to_string),
+        });
+        true
+    }
+
+    /// Labels are matched case-insensitively and with whitespace collapsed.
+    fn find_definition(&self, label: &str) -> Option<&LinkDefinition> {
+        let normalize = |label: &str| {
+            label
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+                .to_lowercase()
+        };
+        let label = normalize(label);
+        self.link_definitions
+            .iter()
+            .find(|definition| normalize(&definition.label) == label)
+    }
+
+    /// Returns the identifier a heading has to be written with as `{#id}`,
+    /// or `None` if the renderer gives it the same one from its text.
+    /// Headings have to be passed in document order, as identifiers made
+    /// from the same text are numbered.
+    pub fn heading_id(&mut self, id: Option<&str>, text: &str) -> Option<String> {
+        // The attribute syntax ends at whitespace or `}`
+        let id =
+            id.filter(|id| !id.is_empty() && !id.contains(|c: char| c.is_whitespace() || c == '}'));
+        match id {
+            Some(id) if id != self.headings.peek(text) => {
+                self.headings.take(id);
+                Some(id.to_string())
+            }
+            _ => {
+                self.headings.slug(text);
+                None
+            }
+        }
+    }
+
+    /// Reports something that couldn't be carried over to the Markdown. Each
+    /// warning is only kept once.
+    pub fn warn(&mut self, warning: ConvertWarning) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Warnings reported so far, in the order they were first reported.
+    pub fn warnings(&self) -> &[ConvertWarning] {
+        &self.warnings
+    }
+
+    /// Gives up on the conversion because the handler's element can't be
+    /// written. `MarkdownConverter::try_convert_to_markdown` returns the
+    /// error, while `MarkdownConverter::convert_to_markdown` carries on
+    /// with the rest of the document.
+    pub fn fail(&mut self, message: impl Into<String>) {
+        let tag = self.ancestors().next_back().unwrap_or_default().to_string();
+        self.set_error(ConvertError::Handler {
+            tag,
+            message: message.into(),
+        });
+    }
+
+    /// Returns true once the conversion has failed.
+    pub fn has_failed(&self) -> bool {
+        self.error.is_some()
+    }
+
+    /// Records an error, unless an earlier one already ended the conversion.
+    pub(crate) fn set_error(&mut self, error: ConvertError) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+    }
+
+    pub(crate) fn take_error(&mut self) -> Option<ConvertError> {
+        self.error.take()
+    }
+
+    pub(crate) fn take_warnings(&mut self) -> Vec<ConvertWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    pub(crate) fn maps_sources(&self) -> bool {
+        self.source_map
+    }
+
+    /// Path below `<body>` of the innermost element being lowered, if a
+    /// source map is being made.
+    pub(crate) fn path(&self) -> Option<&[usize]> {
+        self.ancestors.last()?.path.as_deref()
+    }
+
+    /// Returns the nodes from the innermost element being lowered down to
+    /// `node`, or `None` if `node` isn't inside it.
+    pub(crate) fn lineage(&self, node: &Handle) -> Option<Vec<Handle>> {
+        lineage(&self.ancestors.last()?.node, node)
+    }
+
+    pub(crate) fn push_element(&mut self, name: LocalName, node: Handle, path: Option<Vec<usize>>) {
+        self.ancestors.push(Element { name, node, path });
+    }
+
+    pub(crate) fn pop_element(&mut self) {
+        self.ancestors.pop();
+    }
+
+    pub(crate) fn push_list(&mut self, list: ListContext) {
+        self.lists.push(list);
+    }
+
+    pub(crate) fn pop_list(&mut self) {
+        self.lists.pop();
+    }
+
+    pub(crate) fn current_list(&mut self) -> Option<&mut ListContext> {
+        self.lists.last_mut()
+    }
+}
+
+/// Returns the nodes from `root` down to `node`, both included, or `None` if
+/// `node` isn't inside `root`.
+fn lineage(root: &Handle, node: &Handle) -> Option<Vec<Handle>> {
+    // Handlers mostly lower the children of their own element
+    if root
+        .children
+        .borrow()
+        .iter()
+        .any(|child| Rc::ptr_eq(child, node))
+    {
+        return Some(vec![root.clone(), node.clone()]);
+    }
+
+    // Otherwise search the tree depth first, keeping the nodes on the way
+    // down along with the index of the next child to look at
+    let mut stack = vec![(root.clone(), 0)];
+    while let Some((current, next)) = stack.last_mut() {
+        if Rc::ptr_eq(current, node) {
+            return Some(stack.into_iter().map(|(node, _)| node).collect());
+        }
+        let child = current.children.borrow().get(*next).cloned();
+        *next += 1;
+        match child {
+            Some(child) => stack.push((child, 0)),
+            None => {
+                stack.pop();
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::MarkdownConverter;
+
+    #[test]
+    fn finds_the_siblings_of_elements() {
+        let converter = MarkdownConverter::new();
+        assert_eq!(
+            converter.convert_to_markdown("<p>a<br>b<br> <br></p>"),
+            "a\\\nb"
+        );
+        // Table cells are lowered by the table's handler
+        assert_eq!(
+            converter.convert_to_markdown("<table><tr><td>x<br>y<br></td></tr></table>"),
+            "| x<br>y |\n| ------ |"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Errors and warnings of `MarkdownConverter::try_convert_to_markdown`.
+
+use std::fmt;
+
+/// Why HTML couldn't be converted to Markdown.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConvertError {
+    /// Elements were nested deeper than `ConverterOptions::max_depth`.
+    NestingTooDeep { limit: usize },
+    /// A handler gave up on an element, see `ConversionContext::fail`.
+    Handler { tag: String, message: String },
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::NestingTooDeep { limit } => {
+                write!(f, "Elements are nested more than {} levels deep", limit)
+            }
+            ConvertError::Handler { tag, message } => {
+                write!(f, "Failed to convert <{}>: {}", tag, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+/// Something that was lost in a conversion that otherwise succeeded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConvertWarning {
+    /// An element without a handler was removed, keeping only its content.
+    StrippedElement { tag: String },
+    /// An element was left out together with its content.
+    DroppedElement { tag: String },
+    /// A style has no Markdown equivalent and was left out.
+    UnknownStyle { property: String, value: String },
+}
+
+impl fmt::Display for ConvertWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertWarning::StrippedElement { tag } => {
+                write!(f, "<{}> elements were removed, keeping their text", tag)
+            }
+            ConvertWarning::DroppedElement { tag } => write!(f, "<{}> elements were left out", tag),
+            ConvertWarning::UnknownStyle { property, value } => {
+                write!(f, "The style \"{}: {}\" was left out", property, value)
+            }
+        }
+    }
+}
+
+/// The Markdown written by a successful conversion, with the warnings
+/// reported along the way.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Conversion {
+    pub markdown: String,
+    pub warnings: Vec<ConvertWarning>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConversionContext, ConverterOptions, ElementHandler, MarkdownConverter};
+    use markup5ever_rcdom::Handle;
+
+    struct FailingHandler;
+
+    impl ElementHandler for FailingHandler {
+        fn handle(
+            &self,
+            _converter: &MarkdownConverter,
+            _node: &Handle,
+            _attrs: &[html5ever::Attribute],
+            _output: &mut String,
+            ctx: &mut ConversionContext,
+        ) {
+            ctx.fail("not supported");
+        }
+    }
+
+    fn shallow() -> MarkdownConverter {
+        MarkdownConverter::with_options(ConverterOptions {
+            max_depth: 6,
+            ..ConverterOptions::default()
+        })
+    }
+
+    #[test]
+    fn fails_on_deep_nesting() {
+        let html = "<p>a</p><div><div><div><div><p>deep</p></div></div></div></div><p>b</p>";
+        assert_eq!(
+            shallow().try_convert_to_markdown(html),
+            Err(ConvertError::NestingTooDeep { limit: 6 })
+        );
+        // Only the deep part is left out when the conversion can't fail
+        assert_eq!(shallow().convert_to_markdown(html), "a\n\nb");
+    }
+
+    #[test]
+    fn fails_when_a_handler_gives_up() {
+        let mut converter = MarkdownConverter::new();
+        converter.register_handler("widget", FailingHandler);
+        let html = "<p>a</p><widget>x</widget><p>b</p>";
+        assert_eq!(
+            converter.try_convert_to_markdown(html),
+            Err(ConvertError::Handler {
+                tag: "widget".to_string(),
+                message: "not supported".to_string(),
+            })
+        );
+        assert_eq!(converter.convert_to_markdown(html), "a\n\nb");
+    }
+
+    #[test]
+    fn converts_the_editor_markup_without_warnings() {
+        let conversion = MarkdownConverter::new()
+            .try_convert_with_warnings(
+                "<ul data-type=\"taskList\"><li data-type=\"taskItem\" data-checked=\"true\">\
+                 <label><input type=\"checkbox\" checked><span></span></label>\
+                 <div><p><i>Done</i> and <b>bold</b></p></div></li></ul>",
+            )
+            .unwrap();
+        assert_eq!(conversion.markdown, "- [x] *Done* and **bold**");
+        assert_eq!(conversion.warnings, vec![]);
+    }
+
+    #[test]
+    fn reports_what_was_lost() {
+        let conversion = MarkdownConverter::new()
+            .try_convert_with_warnings(
+                "<p><span style=\"color: red\">a</span> <blink>b</blink> <blink>c</blink></p>\
+                 <label>d</label>",
+            )
+            .unwrap();
+        assert_eq!(conversion.markdown, "a b c\n\nd");
+        assert_eq!(
+            conversion.warnings,
+            vec![
+                ConvertWarning::UnknownStyle {
+                    property: "color".to_string(),
+                    value: "red".to_string(),
+                },
+                ConvertWarning::StrippedElement {
+                    tag: "blink".to_string(),
+                },
+                ConvertWarning::StrippedElement {
+                    tag: "label".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,110 @@
+//! Escaping of Markdown metacharacters in text content.
+//!
+//! Text taken from the DOM is literal, so any character the Markdown parser
+//! would read as syntax has to be escaped before it is written. Escaping is
+//! kept to the minimum that stops the text from being reinterpreted, so the
+//! saved file stays readable.
+
+/// Escapes `text` so that it reads back as the same literal text.
+///
+/// `previous` is the character written just before the text, and `at_line_start`
+/// tells whether the text starts a new line, where block markers such as `#`,
+/// `>` or `1.` would take effect.
+pub(crate) fn escape_text(text: &str, previous: Option<char>, at_line_start: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut previous = previous;
+
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            escaped.push('\n');
+            previous = Some('\n');
+        }
+
+        let line_start = index > 0 || at_line_start;
+        let chars: Vec<char> = line.chars().collect();
+        let marker = if line_start {
+            block_marker(&chars)
+        } else {
+            None
+        };
+
+        for (position, &c) in chars.iter().enumerate() {
+            let next = chars.get(position + 1).copied();
+            let needs_escape = marker == Some(position)
+                || match c {
+                    '\\' => next.is_none_or(|next| next.is_ascii_punctuation()),
+                    // `]` would end the text of a link early
+                    '*' | '`' | '[' | ']' | '~' | '$' => true,
+                    // Underscores inside a word never start emphasis
+                    '_' => {
+                        !(previous.is_some_and(char::is_alphanumeric)
+                            && next.is_some_and(char::is_alphanumeric))
+                    }
+                    // A second `=` would open a highlight
+                    '=' => previous == Some('='),
+                    '<' => next.is_some_and(|next| {
+                        next.is_ascii_alphabetic() || matches!(next, '/' | '!' | '?')
+                    }),
+                    '&' => is_entity(&chars[position + 1..]),
+                    _ => false,
+                };
+
+            if needs_escape {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            previous = Some(c);
+        }
+    }
+
+    escaped
+}
+
+/// Returns the position of a character at the start of a line that would turn
+/// the line into a heading, quote, list item, definition or thematic break.
+fn block_marker(line: &[char]) -> Option<usize> {
+    let start = line.iter().position(|c| *c != ' ' && *c != '\t')?;
+    let rest = &line[start..];
+    let followed_by_space =
+        |offset: usize| rest.get(offset).is_none_or(|c| *c == ' ' || *c == '\t');
+
+    match rest[0] {
+        '#' => {
+            let hashes = rest.iter().take_while(|c| **c == '#').count();
+            (hashes <= 6 && followed_by_space(hashes)).then_some(start)
+        }
+        '>' => Some(start),
+        '-' | '+' | ':' if followed_by_space(1) => Some(start),
+        // Lines of `-` or `=` alone turn the previous line into a heading
+        '-' | '='
+            if rest
+                .iter()
+                .all(|c| *c == rest[0] || *c == ' ' || *c == '\t') =>
+        {
+            Some(start)
+        }
+        '0'..='9' => {
+            let digits = rest.iter().take_while(|c| c.is_ascii_digit()).count();
+            let delimiter = rest.get(digits).copied();
+            (digits <= 9
+                && matches!(delimiter, Some('.') | Some(')'))
+                && followed_by_space(digits + 1))
+            .then_some(start + digits)
+        }
+        _ => None,
+    }
+}
+
+/// Returns true if the text following an `&` would be read as an entity or a
+/// numeric character reference.
+fn is_entity(rest: &[char]) -> bool {
+    let Some(end) = rest.iter().position(|c| *c == ';') else {
+        return false;
+    };
+    let name = &rest[..end];
+    match name.first() {
+        Some('#') => name.len() > 1 && name[1..].iter().all(|c| c.is_ascii_alphanumeric()),
+        Some(_) => name.iter().all(|c| c.is_ascii_alphanumeric()),
+        None => false,
+    }
+}
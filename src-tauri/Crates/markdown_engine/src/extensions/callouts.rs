@@ -0,0 +1,126 @@
+use crate::renderer::{escape_html, RenderExtension, SourceEvent};
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use regex::Regex;
+use std::sync::LazyLock;
+
+static CALLOUT: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\[!([A-Za-z][\w-]*)\]([+-]?)[ \t]*").unwrap());
+
+/// Turns blockquotes starting with `[!TYPE]`, optionally followed by a fold
+/// state (`+` or `-`) and a title, into `div.callout` elements:
+///
+/// ```html
+/// <div class="callout" data-callout="warning" data-callout-kind="Warning" data-callout-fold="-">
+/// <div class="callout-title">Title</div>
+/// <div class="callout-content">...</div>
+/// </div>
+/// ```
+///
+/// `data-callout` has the type in lowercase, for styling, and
+/// `data-callout-kind` as it was written. The marker is looked up in the
+/// source, so an escaped `\[!TYPE]` stays a plain blockquote. Callouts
+/// without a title get the type as their title, marked `data-default-title`.
+pub struct CalloutExtension;
+impl RenderExtension for CalloutExtension {
+    fn transform<'a>(&self, source: &str, events: Vec<SourceEvent<'a>>) -> Vec<SourceEvent<'a>> {
+        let mut rendered = Vec::with_capacity(events.len());
+        // Whether each open blockquote is a callout, innermost last
+        let mut quotes = Vec::new();
+        let mut events = events.into_iter().peekable();
+
+        while let Some((event, range)) = events.next() {
+            match &event {
+                Event::Start(Tag::BlockQuote(_)) => {
+                    let paragraph = match events.peek() {
+                        Some((Event::Start(Tag::Paragraph), paragraph)) => paragraph.clone(),
+                        _ => {
+                            quotes.push(false);
+                            rendered.push((event, range));
+                            continue;
+                        }
+                    };
+                    let first_line = source[paragraph.start..].lines().next().unwrap_or_default();
+                    let Some(marker) = CALLOUT.captures(first_line) else {
+                        quotes.push(false);
+                        rendered.push((event, range));
+                        continue;
+                    };
+                    let kind = marker[1].to_ascii_lowercase();
+                    let fold = &marker[2];
+                    let marker_end = paragraph.start + marker[0].len();
+                    events.next();
+
+                    let mut html = format!(
+                        r#"<div class="callout" data-callout="{}" data-callout-kind="{}""#,
+                        escape_html(&kind),
+                        escape_html(&marker[1])
+                    );
+                    if !fold.is_empty() {
+                        html.push_str(&format!(r#" data-callout-fold="{fold}""#));
+                    }
+                    html.push_str(">\n");
+
+                    // The rest of the first line is the title
+                    let mut title = Vec::new();
+                    while let Some((next, _)) = events.peek() {
+                        if matches!(
+                            next,
+                            Event::SoftBreak | Event::HardBreak | Event::End(TagEnd::Paragraph)
+                        ) {
+                            break;
+                        }
+                        let (next, next_range) = events.next().unwrap();
+                        if next_range.end <= marker_end {
+                            continue;
+                        }
+                        match next {
+                            Event::Text(text) if next_range.start < marker_end => {
+                                let skip = text.len().saturating_sub(next_range.end - marker_end);
+                                let text =
+                                    text.get(skip..).unwrap_or(&text).trim_start().to_string();
+                                title.push((
+                                    Event::Text(CowStr::from(text)),
+                                    marker_end..next_range.end,
+                                ));
+                            }
+                            next => title.push((next, next_range)),
+                        }
+                    }
+                    if title.is_empty() {
+                        let mut default = kind.clone();
+                        default[..1].make_ascii_uppercase();
+                        html.push_str(r#"<div class="callout-title" data-default-title>"#);
+                        title.push((Event::Text(CowStr::from(default)), range.clone()));
+                    } else {
+                        html.push_str(r#"<div class="callout-title">"#);
+                    }
+                    rendered.push((Event::Html(CowStr::from(html)), range.clone()));
+                    rendered.extend(title);
+                    rendered.push((
+                        Event::Html(CowStr::from("</div>\n<div class=\"callout-content\">\n")),
+                        range.clone(),
+                    ));
+
+                    // Whatever follows the first line stays a paragraph of the body
+                    if matches!(events.peek(), Some((Event::End(TagEnd::Paragraph), _))) {
+                        events.next();
+                    } else {
+                        events.next();
+                        rendered.push((Event::Start(Tag::Paragraph), paragraph));
+                    }
+                    quotes.push(true);
+                }
+                Event::End(TagEnd::BlockQuote(_)) => {
+                    if quotes.pop() == Some(true) {
+                        rendered.push((Event::Html(CowStr::from("</div>\n</div>\n")), range));
+                    } else {
+                        rendered.push((event, range));
+                    }
+                }
+                _ => rendered.push((event, range)),
+            }
+        }
+
+        rendered
+    }
+}
@@ -0,0 +1,153 @@
+use crate::renderer::{RenderExtension, SourceEvent};
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use std::collections::HashMap;
+
+/// Turns `==highlighted==` text into `<mark>` elements.
+///
+/// The two `==` may be in different text events, as in `==**bold**==`, as long
+/// as they are in the same block and at the same inline nesting level, so the
+/// HTML stays well-formed. An opening `==` has to be followed and a closing one
+/// preceded by something other than whitespace. Code and escaped `\==` are
+/// left alone.
+pub struct HighlightExtension;
+
+/// A `==` found in a text event.
+struct Marker {
+    event: usize,
+    offset: usize,
+    can_open: bool,
+    can_close: bool,
+    // The block and inline nesting level the marker is in
+    scope: (usize, usize),
+}
+
+impl RenderExtension for HighlightExtension {
+    fn transform<'a>(&self, source: &str, events: Vec<SourceEvent<'a>>) -> Vec<SourceEvent<'a>> {
+        let markers = find_markers(source, &events);
+
+        // Pair each closing marker with the last unpaired opening one in its scope
+        let mut open: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut pairs: HashMap<(usize, usize), &'static str> = HashMap::new();
+        for (index, marker) in markers.iter().enumerate() {
+            if marker.can_close {
+                if let Some(opening) = open.remove(&marker.scope) {
+                    let opening = &markers[opening];
+                    pairs.insert((opening.event, opening.offset), "<mark>");
+                    pairs.insert((marker.event, marker.offset), "</mark>");
+                    continue;
+                }
+            }
+            if marker.can_open {
+                open.insert(marker.scope, index);
+            }
+        }
+        if pairs.is_empty() {
+            return events;
+        }
+
+        let mut rendered = Vec::with_capacity(events.len());
+        for (index, (event, range)) in events.into_iter().enumerate() {
+            let Event::Text(text) = &event else {
+                rendered.push((event, range));
+                continue;
+            };
+
+            let mut last = 0;
+            for (offset, html) in text
+                .match_indices("==")
+                .filter_map(|(offset, _)| pairs.get(&(index, offset)).map(|html| (offset, *html)))
+            {
+                if offset > last {
+                    let piece = text[last..offset].to_string();
+                    rendered.push((Event::Text(CowStr::from(piece)), range.clone()));
+                }
+                rendered.push((Event::InlineHtml(CowStr::from(html)), range.clone()));
+                last = offset + 2;
+            }
+            if last == 0 {
+                rendered.push((event, range));
+            } else if last < text.len() {
+                let piece = text[last..].to_string();
+                rendered.push((Event::Text(CowStr::from(piece)), range));
+            }
+        }
+
+        rendered
+    }
+}
+
+/// Finds every `==` in text outside of code that was written as is in the source.
+fn find_markers(source: &str, events: &[SourceEvent]) -> Vec<Marker> {
+    let mut markers = Vec::new();
+    let mut block = 0;
+    let mut depth = 0;
+    let mut in_code_block = false;
+
+    for (index, (event, range)) in events.iter().enumerate() {
+        match event {
+            Event::Start(tag) => {
+                if is_inline(tag) {
+                    depth += 1;
+                } else {
+                    block += 1;
+                    depth = 0;
+                    in_code_block = matches!(tag, Tag::CodeBlock(_));
+                }
+            }
+            Event::End(tag) => {
+                if is_inline_end(tag) {
+                    depth -= 1;
+                } else {
+                    block += 1;
+                    depth = 0;
+                    in_code_block = false;
+                }
+            }
+            Event::Text(text) if !in_code_block && source.get(range.clone()) == Some(text) => {
+                for (offset, _) in text.match_indices("==") {
+                    // Longer runs of `=` aren't highlight markers
+                    let before = text[..offset].chars().next_back();
+                    let after = text[offset + 2..].chars().next();
+                    if before == Some('=') || after == Some('=') {
+                        continue;
+                    }
+                    let escaped = source[..range.start + offset]
+                        .chars()
+                        .rev()
+                        .take_while(|c| *c == '\\')
+                        .count()
+                        % 2
+                        == 1;
+                    if escaped {
+                        continue;
+                    }
+
+                    markers.push(Marker {
+                        event: index,
+                        offset,
+                        can_open: after.is_none_or(|c| !c.is_whitespace()),
+                        can_close: before.is_none_or(|c| !c.is_whitespace()),
+                        scope: (block, depth),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    markers
+}
+
+fn is_inline(tag: &Tag) -> bool {
+    matches!(
+        tag,
+        Tag::Emphasis | Tag::Strong | Tag::Strikethrough | Tag::Link { .. } | Tag::Image { .. }
+    )
+}
+
+fn is_inline_end(tag: &TagEnd) -> bool {
+    matches!(
+        tag,
+        TagEnd::Emphasis | TagEnd::Strong | TagEnd::Strikethrough | TagEnd::Link | TagEnd::Image
+    )
+}
@@ -0,0 +1,109 @@
+use crate::renderer::{escape_html, RenderExtension, SourceEvent};
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+/// Gives images written in the Obsidian form `![alt|300](src)` or
+/// `![alt|300x200](src)` that width and height, which is how markdown_engine
+/// writes sized images. An escaped `\|` is part of the alt text.
+pub struct ImageSizeExtension;
+impl RenderExtension for ImageSizeExtension {
+    fn transform<'a>(&self, source: &str, events: Vec<SourceEvent<'a>>) -> Vec<SourceEvent<'a>> {
+        let mut rendered = Vec::with_capacity(events.len());
+        let mut events = events.into_iter();
+
+        while let Some((event, range)) = events.next() {
+            let Event::Start(Tag::Image {
+                dest_url, title, ..
+            }) = &event
+            else {
+                rendered.push((event, range));
+                continue;
+            };
+            let (dest_url, title) = (dest_url.to_string(), title.to_string());
+
+            // Gather the alt text, up to the end of this image
+            let mut alt_events = Vec::new();
+            let mut end = None;
+            let mut depth = 0;
+            for (inner, inner_range) in events.by_ref() {
+                match &inner {
+                    Event::Start(Tag::Image { .. }) => depth += 1,
+                    Event::End(TagEnd::Image) if depth == 0 => {
+                        end = Some((inner, inner_range));
+                        break;
+                    }
+                    Event::End(TagEnd::Image) => depth -= 1,
+                    _ => {}
+                }
+                alt_events.push((inner, inner_range));
+            }
+
+            match sized_alt(source, &alt_events) {
+                Some((alt, size)) => {
+                    let (width, height) = size.split_once('x').unwrap_or((size, ""));
+                    let mut html = format!(
+                        r#"<img src="{}" alt="{}""#,
+                        escape_html(&dest_url),
+                        escape_html(&alt)
+                    );
+                    if !title.is_empty() {
+                        html.push_str(&format!(r#" title="{}""#, escape_html(&title)));
+                    }
+                    html.push_str(&format!(r#" width="{}""#, width));
+                    if !height.is_empty() {
+                        html.push_str(&format!(r#" height="{}""#, height));
+                    }
+                    html.push_str(" />");
+                    rendered.push((Event::InlineHtml(CowStr::from(html)), range));
+                }
+                None => {
+                    rendered.push((event, range));
+                    rendered.extend(alt_events);
+                    rendered.extend(end);
+                }
+            }
+        }
+
+        rendered
+    }
+}
+
+/// Returns the alt text of an image and the size at its end, if the size was
+/// written as is in the source.
+fn sized_alt<'s>(source: &str, alt_events: &'s [SourceEvent]) -> Option<(String, &'s str)> {
+    let (Event::Text(last), range) = alt_events.last()? else {
+        return None;
+    };
+    if source.get(range.clone()) != Some(last.as_ref()) {
+        return None;
+    }
+    let (before, size) = split_image_size(last)?;
+    let escaped = source[..range.start + before.len()]
+        .chars()
+        .rev()
+        .take_while(|c| *c == '\\')
+        .count()
+        % 2
+        == 1;
+    if escaped {
+        return None;
+    }
+
+    let mut alt = String::new();
+    for (event, _) in &alt_events[..alt_events.len() - 1] {
+        match event {
+            Event::Text(text) | Event::Code(text) => alt.push_str(text),
+            Event::SoftBreak | Event::HardBreak => alt.push(' '),
+            _ => {}
+        }
+    }
+    alt.push_str(before);
+    Some((alt, size))
+}
+
+/// Splits `alt|300` or `alt|300x200` into the alt text and the size.
+pub(crate) fn split_image_size(alt: &str) -> Option<(&str, &str)> {
+    let (alt, size) = alt.rsplit_once('|')?;
+    let (width, height) = size.split_once('x').unwrap_or((size, "0"));
+    let is_number = |value: &str| !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit());
+    (is_number(width) && is_number(height)).then_some((alt, size))
+}
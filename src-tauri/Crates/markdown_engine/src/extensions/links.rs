@@ -0,0 +1,39 @@
+use crate::renderer::{escape_html, RenderExtension, SourceEvent};
+use pulldown_cmark::{CowStr, Event, LinkType, Tag, TagEnd};
+
+/// Keeps the label of links written as `[text][label]`, `[label][]` or
+/// `[label]` in a `data-reference` attribute, so markdown_engine writes them
+/// back as reference links.
+pub struct ReferenceLinkExtension;
+impl RenderExtension for ReferenceLinkExtension {
+    fn transform<'a>(&self, _source: &str, events: Vec<SourceEvent<'a>>) -> Vec<SourceEvent<'a>> {
+        let mut rendered = Vec::with_capacity(events.len());
+        let mut in_reference = false;
+
+        for (event, range) in events {
+            match event {
+                Event::Start(Tag::Link {
+                    link_type: LinkType::Reference | LinkType::Collapsed | LinkType::Shortcut,
+                    dest_url,
+                    title,
+                    id,
+                }) => {
+                    let mut html = format!(r#"<a href="{}""#, escape_html(&dest_url));
+                    if !title.is_empty() {
+                        html.push_str(&format!(r#" title="{}""#, escape_html(&title)));
+                    }
+                    html.push_str(&format!(r#" data-reference="{}">"#, escape_html(&id)));
+                    rendered.push((Event::InlineHtml(CowStr::from(html)), range));
+                    in_reference = true;
+                }
+                Event::End(TagEnd::Link) if in_reference => {
+                    rendered.push((Event::InlineHtml(CowStr::from("</a>")), range));
+                    in_reference = false;
+                }
+                event => rendered.push((event, range)),
+            }
+        }
+
+        rendered
+    }
+}
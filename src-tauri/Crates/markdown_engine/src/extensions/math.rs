@@ -0,0 +1,43 @@
+use crate::renderer::{escape_html, RenderExtension, SourceEvent};
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+/// Turns math events into `span.math-inline` and `div.math-display` elements
+/// holding the original TeX, which markdown_engine writes back unchanged.
+/// Display math that makes up a whole paragraph replaces the paragraph, while
+/// display math within other text becomes a `span.math-display`.
+pub struct MathExtension;
+impl RenderExtension for MathExtension {
+    fn transform<'a>(&self, _source: &str, events: Vec<SourceEvent<'a>>) -> Vec<SourceEvent<'a>> {
+        let mut rendered = Vec::with_capacity(events.len());
+        let mut index = 0;
+
+        while index < events.len() {
+            if let (
+                (Event::Start(Tag::Paragraph), range),
+                Some((Event::DisplayMath(tex), _)),
+                Some((Event::End(TagEnd::Paragraph), _)),
+            ) = (&events[index], events.get(index + 1), events.get(index + 2))
+            {
+                rendered.push((math_html(tex, "div", "math-display"), range.clone()));
+                index += 3;
+                continue;
+            }
+
+            let (event, range) = &events[index];
+            let event = match event {
+                Event::InlineMath(tex) => math_html(tex, "span", "math-inline"),
+                Event::DisplayMath(tex) => math_html(tex, "span", "math-display"),
+                event => event.clone(),
+            };
+            rendered.push((event, range.clone()));
+            index += 1;
+        }
+
+        rendered
+    }
+}
+
+fn math_html(tex: &str, tag: &str, class: &str) -> Event<'static> {
+    let html = format!(r#"<{tag} class="{class}">{}</{tag}>"#, escape_html(tex));
+    Event::InlineHtml(CowStr::from(html))
+}
@@ -0,0 +1,17 @@
+//! This module has the extensions that HtmlRenderer applies to pulldown-cmark's events.
+
+pub mod callouts;
+pub mod highlight;
+pub mod images;
+pub mod links;
+pub mod math;
+pub mod tags;
+pub mod wikilinks;
+
+pub use callouts::CalloutExtension;
+pub use highlight::HighlightExtension;
+pub use images::ImageSizeExtension;
+pub use links::ReferenceLinkExtension;
+pub use math::MathExtension;
+pub use tags::TagExtension;
+pub use wikilinks::WikiLinkExtension;
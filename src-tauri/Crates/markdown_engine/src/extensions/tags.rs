@@ -0,0 +1,27 @@
+use crate::renderer::{escape_html, InlineExtension};
+use regex::Regex;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+// A tag has at least one character that isn't a digit, so `#1` isn't a tag
+static TAG: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|\s)(#[\p{L}\p{N}_/-]*[\p{L}_/-][\p{L}\p{N}_/-]*)").unwrap());
+
+/// Turns `#tag` and `#nested/tag` into `span.tag` elements. Tags start a word,
+/// so the `#` in `C#` or a URL isn't one.
+pub struct TagExtension;
+impl InlineExtension for TagExtension {
+    fn find(&self, text: &str) -> Option<Range<usize>> {
+        TAG.captures(text)
+            .and_then(|captures| captures.get(1))
+            .map(|tag| tag.range())
+    }
+
+    fn render(&self, source: &str) -> String {
+        format!(
+            r#"<span class="tag" data-tag="{}">{}</span>"#,
+            escape_html(&source[1..]),
+            escape_html(source)
+        )
+    }
+}
@@ -0,0 +1,41 @@
+use crate::renderer::{escape_html, InlineExtension};
+use regex::Regex;
+use std::ops::Range;
+use std::sync::LazyLock;
+
+static WIKILINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[\[([^\[\]\n]+?)\]\]").unwrap());
+
+/// Turns `[[Note]]`, `[[Note|alias]]` and `[[Note#Heading]]` into
+/// `a.wikilink` elements, which markdown_engine writes back as wikilinks.
+pub struct WikiLinkExtension;
+impl InlineExtension for WikiLinkExtension {
+    fn find(&self, text: &str) -> Option<Range<usize>> {
+        WIKILINK.find(text).map(|found| found.range())
+    }
+
+    fn render(&self, source: &str) -> String {
+        let inner = &source[2..source.len() - 2];
+        let (target, alias) = match inner.split_once('|') {
+            Some((target, alias)) => (target, Some(alias)),
+            None => (inner, None),
+        };
+        let (note, heading) = match target.split_once('#') {
+            Some((note, heading)) => (note, Some(heading)),
+            None => (target, None),
+        };
+
+        let mut html = format!(
+            r#"<a class="wikilink" href="{}" data-target="{}""#,
+            escape_html(target),
+            escape_html(note)
+        );
+        if let Some(heading) = heading {
+            html.push_str(&format!(r#" data-heading="{}""#, escape_html(heading)));
+        }
+        if let Some(alias) = alias {
+            html.push_str(&format!(r#" data-alias="{}""#, escape_html(alias)));
+        }
+        html.push_str(&format!(">{}</a>", escape_html(alias.unwrap_or(target))));
+        html
+    }
+}
@@ -0,0 +1,125 @@
+//! Splitting YAML and TOML frontmatter off the start of a note and putting
+//! it back.
+
+/// A frontmatter block at the start of a note, with every part kept exactly
+/// as it is in the note, line endings included.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrontmatterBlock<'a> {
+    /// The opening fence line without its `\n`: `---` for YAML, `+++` for
+    /// TOML.
+    pub fence: &'a str,
+    /// The text between the fences.
+    pub raw: &'a str,
+    /// The closing fence line without its `\n`. YAML blocks may also be
+    /// closed by `...`.
+    pub closing: &'a str,
+    /// The line break after the closing fence and any blank lines after it.
+    pub separator: &'a str,
+}
+
+impl FrontmatterBlock<'_> {
+    /// Returns true for TOML frontmatter and false for YAML.
+    pub fn is_toml(&self) -> bool {
+        self.fence.trim_end() == "+++"
+    }
+}
+
+/// Splits a frontmatter block off the start of a note, returning it and the
+/// rest of the note. A block that is never closed is just the start of the
+/// note.
+pub fn split_frontmatter(markdown: &str) -> Option<(FrontmatterBlock<'_>, &str)> {
+    let (fence, mut rest) = markdown.split_once('\n')?;
+    let closing: &[&str] = match fence.trim_end() {
+        "---" => &["---", "..."],
+        "+++" => &["+++"],
+        _ => return None,
+    };
+
+    // Find the closing fence, keeping the lines before it as they are
+    let raw_start = markdown.len() - rest.len();
+    while !rest.is_empty() {
+        let (line, next) = rest.split_once('\n').unwrap_or((rest, ""));
+        if closing.contains(&line.trim_end()) {
+            let raw = &markdown[raw_start..markdown.len() - rest.len()];
+            let after = &rest[line.len()..];
+            let body = after.trim_start_matches(['\n', '\r']);
+            let separator = &after[..after.len() - body.len()];
+            let block = FrontmatterBlock {
+                fence,
+                raw,
+                closing: line,
+                separator,
+            };
+            return Some((block, body));
+        }
+        rest = next;
+    }
+    None
+}
+
+/// Puts a frontmatter block back in front of the body of a note, exactly as
+/// `split_frontmatter` found it.
+pub fn join_frontmatter(frontmatter: FrontmatterBlock, body: &str) -> String {
+    let mut markdown = format!(
+        "{}\n{}{}{}",
+        frontmatter.fence, frontmatter.raw, frontmatter.closing, frontmatter.separator
+    );
+    // A body added to a note that ended with the closing fence starts on a
+    // line of its own
+    if !body.is_empty() && !markdown.ends_with('\n') {
+        markdown.push('\n');
+    }
+    markdown.push_str(body);
+    markdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[track_caller]
+    fn assert_round_trip(markdown: &str) {
+        let (block, body) = split_frontmatter(markdown).unwrap();
+        assert_eq!(join_frontmatter(block, body), markdown);
+    }
+
+    #[test]
+    fn splits_yaml_and_toml() {
+        let (block, body) = split_frontmatter("---\ntags: [a]\n---\n\n# Note").unwrap();
+        assert_eq!(block.fence, "---");
+        assert_eq!(block.raw, "tags: [a]\n");
+        assert_eq!(block.closing, "---");
+        assert_eq!(block.separator, "\n\n");
+        assert!(!block.is_toml());
+        assert_eq!(body, "# Note");
+
+        let (block, body) = split_frontmatter("+++\ntitle = \"a\"\n+++\nText").unwrap();
+        assert_eq!(block.raw, "title = \"a\"\n");
+        assert!(block.is_toml());
+        assert_eq!(body, "Text");
+    }
+
+    #[test]
+    fn leaves_notes_without_a_closed_block_alone() {
+        assert_eq!(split_frontmatter("# Note\n---\n"), None);
+        assert_eq!(split_frontmatter("---\ntags: [a]\n"), None);
+        assert_eq!(split_frontmatter("+++\ntitle = 1\n---\n"), None);
+    }
+
+    #[test]
+    fn writes_blocks_back_exactly() {
+        assert_round_trip("---\ntags: [a]\n---\n\n# Note");
+        assert_round_trip("---\ntags: [a]\n---\n# Note");
+        assert_round_trip("---\ntags: [a]\n...\n\n\nText");
+        assert_round_trip("---\r\ntags: [a]\r\n---\r\n\r\nText");
+        assert_round_trip("+++\ntitle = \"a\"\n+++\n");
+        assert_round_trip("---\n---\n");
+        assert_round_trip("---\ntags: [a]\n---");
+    }
+
+    #[test]
+    fn starts_a_new_body_on_its_own_line() {
+        let (block, _) = split_frontmatter("---\ntags: [a]\n---").unwrap();
+        assert_eq!(join_frontmatter(block, "Text"), "---\ntags: [a]\n---\nText");
+    }
+}
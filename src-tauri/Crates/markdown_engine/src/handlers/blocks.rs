@@ -1,3 +1,7 @@
+use super::collect_text;
+use crate::ast::{self, Block, Node};
+use crate::ConversionContext;
+use crate::ConverterOptions;
 use crate::ElementHandler;
 // use html5ever::Attribute;
 use crate::MarkdownConverter;
@@ -5,143 +9,137 @@ use markup5ever_rcdom::{Handle, NodeData};
 
 pub struct DivHandler;
 impl ElementHandler for DivHandler {
-    fn handle(
+    fn lower(
         &self,
         converter: &MarkdownConverter,
         node: &Handle,
-        attrs: &[html5ever::Attribute],
-        output: &mut String,
-        depth: usize,
-    ) {
-        // Check if the parent node is a list item
-        let is_within_list_item = if let Some(parent) = node.parent.take() {
-            if let NodeData::Element { name, .. } = &parent.upgrade().unwrap().data {
-                name.local.as_ref() == "li"
-            } else {
-                false
-            }
-        } else {
-            false
-        };
-
-        if is_within_list_item {
-            // If within a list item, avoid adding extra newlines
-            converter.walk_children(node, output, depth);
-        } else {
-            // Standard paragraph handling with newlines
-
-            // Check if it's an editor-line div
-            let is_editor_line = attrs.iter().any(|attr| {
-                attr.name.local.as_ref() == "class" && attr.value.as_ref().contains("editor-line")
-            });
-
-            if is_editor_line {
-                output.push('\n');
-            }
-
-            converter.walk_children(node, output, depth);
-
-            // if is_editor_line {
-            //     output.push('\n');
-            // }
+        _attrs: &[html5ever::Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let children = converter.lower_children(node, ctx);
+        if ctx.parent() == Some("li") {
+            return children;
         }
+
+        // Outside of list items a div is a block, so its text is a paragraph of its own
+        ast::into_blocks(children)
+            .into_iter()
+            .map(Node::Block)
+            .collect()
     }
 }
 
 pub struct ParagraphHandler;
 impl ElementHandler for ParagraphHandler {
-    fn handle(
+    fn lower(
         &self,
         converter: &MarkdownConverter,
         node: &Handle,
         _attrs: &[html5ever::Attribute],
-        output: &mut String,
-        depth: usize,
-    ) {
-        // Check if the parent node is a list item
-        let is_within_list_item = if let Some(parent) = node.parent.take() {
-            if let NodeData::Element { name, .. } = &parent.upgrade().unwrap().data {
-                name.local.as_ref() == "li"
-            } else {
-                false
-            }
-        } else {
-            false
-        };
-
-        if is_within_list_item {
-            // If within a list item, avoid adding extra newlines
-            converter.walk_children(node, output, depth);
-        } else {
-            // Standard paragraph handling with newlines
-            output.push_str("\n");
-            converter.walk_children(node, output, depth);
-            output.push_str("\n");
-        }
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let children = converter.lower_inlines(node, ctx);
+        vec![Node::Block(Block::Paragraph(children))]
     }
 }
 
 pub struct BlockquoteHandler;
 impl ElementHandler for BlockquoteHandler {
-    fn handle(
+    fn lower(
         &self,
         converter: &MarkdownConverter,
         node: &Handle,
         _attrs: &[html5ever::Attribute],
-        output: &mut String,
-        depth: usize,
-    ) {
-        // Add newline before blockquote if not at start
-        // if !output.is_empty() && !output.ends_with('\n') {
-        output.push('\n');
-        // }
-
-        // Create a temporary buffer for the quote content
-        let mut quote_content = String::new();
-        converter.walk_children(node, &mut quote_content, depth + 1);
-
-        // Process the quote content line by line
-        for line in quote_content.trim().lines() {
-            output.push_str("> ");
-            output.push_str(line);
-            output.push('\n');
-        }
-
-        // Add extra newline after blockquote(optional)
-        // output.push('\n');
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let children = converter.lower_blocks(node, ctx);
+        vec![Node::Block(Block::BlockQuote(children))]
     }
 }
 
+/// Writes `<pre>` as a fenced code block.
+///
+/// The code is taken as raw text, so markup from syntax highlighting doesn't
+/// end up in the block. The fence is made longer than any run of fence
+/// characters in the code. The info string comes from a `data-info` attribute
+/// when there is one, which keeps attributes such as `rust title="x"`, or else
+/// from a `language-` class.
 pub struct CodeBlockHandler;
 impl ElementHandler for CodeBlockHandler {
-    fn handle(
+    fn lower(
         &self,
-        converter: &MarkdownConverter,
+        _converter: &MarkdownConverter,
         node: &Handle,
-        _attrs: &[html5ever::Attribute],
-        output: &mut String,
-        depth: usize,
-    ) {
-        // Get the code element which should be the child
-        if let Some(code_node) = node.children.borrow().first() {
-            if let NodeData::Element { attrs, .. } = &code_node.data {
-                output.push_str("\n```");
+        attrs: &[html5ever::Attribute],
+        _ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let (code, info) = code_block(node, attrs);
+        vec![Node::Block(Block::CodeBlock { info, code })]
+    }
+}
 
-                // Extract language if present
-                for attr in attrs.borrow().iter() {
-                    if attr.name.local.as_ref() == "class" {
-                        if let Some(lang) = attr.value.as_ref().strip_prefix("language-") {
-                            output.push_str(lang);
-                            break;
-                        }
-                    }
-                }
-                output.push('\n');
+/// Returns the code and the info string of a `<pre>` element.
+fn code_block(node: &Handle, attrs: &[html5ever::Attribute]) -> (String, String) {
+    // The code is usually wrapped in a <code> element, but a bare <pre> is
+    // a code block too
+    let code_node = node
+        .children
+        .borrow()
+        .iter()
+        .find(|child| {
+            matches!(&child.data, NodeData::Element { name, .. } if name.local.as_ref() == "code")
+        })
+        .cloned();
 
-                // Process the actual code content
-                converter.walk_children(code_node, output, depth);
-                output.push_str("\n```\n");
-            }
-        }
+    let mut info = None;
+    if let Some(NodeData::Element { attrs, .. }) = code_node.as_ref().map(|code| &code.data) {
+        info = info_string(&attrs.borrow());
+    }
+    let info = info.or_else(|| info_string(attrs)).unwrap_or_default();
+
+    let mut code = String::new();
+    collect_text(code_node.as_ref().unwrap_or(node), &mut code);
+    // The last line of the code ends with a newline of its own
+    if code.ends_with('\n') {
+        code.pop();
+    }
+    (code, info)
+}
+
+/// Writes a fenced code block on lines of its own.
+pub(crate) fn write_code_block(
+    output: &mut String,
+    options: &ConverterOptions,
+    code: &str,
+    info: &str,
+) {
+    let fence = options.fence(code, info);
+
+    output.push('\n');
+    output.push_str(&fence);
+    output.push_str(info);
+    output.push('\n');
+    if !code.is_empty() {
+        output.push_str(code);
+        output.push('\n');
     }
+    output.push_str(&fence);
+    output.push('\n');
+}
+
+/// Returns the info string of a code block from its `data-info` attribute or
+/// its `language-` class.
+fn info_string(attrs: &[html5ever::Attribute]) -> Option<String> {
+    let attr = |name: &str| attrs.iter().find(|attr| attr.name.local.as_ref() == name);
+    if let Some(info) = attr("data-info") {
+        return Some(info.value.replace('\n', " ").trim().to_string());
+    }
+
+    attr("class").and_then(|class| {
+        class
+            .value
+            .split_whitespace()
+            .find_map(|class| class.strip_prefix("language-"))
+            .map(str::to_string)
+    })
 }
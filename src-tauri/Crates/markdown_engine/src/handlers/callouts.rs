@@ -0,0 +1,128 @@
+use super::has_class;
+use crate::ast::{self, Block, Inline, Node};
+use crate::ConversionContext;
+use crate::ElementHandler;
+use crate::MarkdownConverter;
+use html5ever::Attribute;
+use markup5ever_rcdom::{Handle, NodeData};
+
+/// Writes `div.callout` elements back as Obsidian/GitHub callouts:
+///
+/// ```text
+/// > [!WARNING]- Title
+/// > Content
+/// ```
+///
+/// The type comes from `data-callout-kind`, as it was written, or else from
+/// `data-callout` in uppercase. The fold state comes from `data-callout-fold`.
+/// The title is read from a `div.callout-title` child and left out when it is
+/// the default title the renderer marks with `data-default-title`. Everything
+/// else, usually wrapped in a `div.callout-content`, is written as the quoted
+/// body.
+pub struct CalloutHandler;
+impl ElementHandler for CalloutHandler {
+    fn lower(
+        &self,
+        converter: &MarkdownConverter,
+        node: &Handle,
+        attrs: &[Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let (kind, fold) = callout_kind(attrs);
+
+        let mut title = Vec::new();
+        let mut content = Vec::new();
+        for child in node.children.borrow().iter() {
+            if let NodeData::Element { attrs, .. } = &child.data {
+                let attrs = attrs.borrow();
+                if has_class(&attrs, "callout-title") {
+                    let is_default = attrs
+                        .iter()
+                        .any(|attr| attr.name.local.as_ref() == "data-default-title");
+                    title = converter.lower_inlines(child, ctx);
+                    if is_default
+                        && Inline::plain_text(&title)
+                            .trim()
+                            .eq_ignore_ascii_case(&kind)
+                    {
+                        title.clear();
+                    }
+                    continue;
+                }
+                if has_class(&attrs, "callout-content") {
+                    content.extend(converter.lower_children(child, ctx));
+                    continue;
+                }
+            }
+            content.extend(converter.lower_node(child, ctx));
+        }
+
+        vec![Node::Block(Block::Callout {
+            kind,
+            fold,
+            title,
+            children: ast::into_blocks(content),
+        })]
+    }
+}
+
+/// Returns the type of a callout and its fold state, `+` or `-`.
+fn callout_kind(attrs: &[Attribute]) -> (String, Option<char>) {
+    let attr = |name: &str| {
+        attrs
+            .iter()
+            .find(|attr| attr.name.local.as_ref() == name)
+            .map(|attr| attr.value.trim().to_string())
+    };
+    let kind = attr("data-callout-kind")
+        .filter(|kind| !kind.is_empty())
+        .or_else(|| attr("data-callout").map(|kind| kind.to_ascii_uppercase()))
+        .filter(|kind| !kind.is_empty())
+        .unwrap_or_else(|| "NOTE".to_string());
+    let fold = attr("data-callout-fold").and_then(|fold| match fold.as_str() {
+        "+" => Some('+'),
+        "-" => Some('-'),
+        _ => None,
+    });
+    (kind, fold)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::convert_to_markdown;
+
+    #[test]
+    fn writes_the_type_as_it_was_written() {
+        assert_eq!(
+            convert_to_markdown(
+                "<div class=\"callout\" data-callout=\"note\" data-callout-kind=\"note\">\
+                 <div class=\"callout-title\" data-default-title>Note</div>\
+                 <div class=\"callout-content\"><p>Body</p></div></div>"
+            ),
+            "> [!note]\n> Body"
+        );
+        // HTML from elsewhere only has the type in lowercase
+        assert_eq!(
+            convert_to_markdown("<div class=\"callout\" data-callout=\"tip\"><p>Body</p></div>"),
+            "> [!TIP]\n> Body"
+        );
+    }
+
+    #[test]
+    fn keeps_titles_unless_they_are_the_default() {
+        assert_eq!(
+            convert_to_markdown(
+                "<div class=\"callout\" data-callout=\"tip\" data-callout-kind=\"TIP\" \
+                 data-callout-fold=\"-\"><div class=\"callout-title\">Tip</div></div>"
+            ),
+            "> [!TIP]- Tip"
+        );
+        assert_eq!(
+            convert_to_markdown(
+                "<div class=\"callout\" data-callout=\"tip\" data-callout-kind=\"TIP\">\
+                 <div class=\"callout-title\" data-default-title>Renamed</div></div>"
+            ),
+            "> [!TIP] Renamed"
+        );
+    }
+}
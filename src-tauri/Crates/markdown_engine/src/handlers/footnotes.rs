@@ -0,0 +1,89 @@
+use super::has_class;
+use crate::ast::{self, Block, Inline, Node};
+use crate::ConversionContext;
+use crate::ElementHandler;
+use crate::MarkdownConverter;
+use html5ever::Attribute;
+use markup5ever_rcdom::{Handle, NodeData};
+
+/// Writes pulldown-cmark's `<sup class="footnote-reference">` markup back as
+/// `[^label]`.
+pub struct FootnoteReferenceHandler;
+impl ElementHandler for FootnoteReferenceHandler {
+    fn lower(
+        &self,
+        converter: &MarkdownConverter,
+        node: &Handle,
+        _attrs: &[Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        match reference_label(node) {
+            Some(label) => vec![Node::Inline(Inline::FootnoteReference(label))],
+            None => converter.lower_children(node, ctx),
+        }
+    }
+}
+
+/// Writes pulldown-cmark's `<div class="footnote-definition" id="label">`
+/// markup back as `[^label]: text`, indenting any further paragraphs so they
+/// stay part of the definition.
+pub struct FootnoteDefinitionHandler;
+impl ElementHandler for FootnoteDefinitionHandler {
+    fn lower(
+        &self,
+        converter: &MarkdownConverter,
+        node: &Handle,
+        attrs: &[Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let mut children = Vec::new();
+        for child in definition_content(node) {
+            children.extend(converter.lower_node(&child, ctx));
+        }
+
+        vec![Node::Block(Block::FootnoteDefinition {
+            label: definition_label(attrs),
+            children: ast::into_blocks(children),
+        })]
+    }
+}
+
+fn definition_label(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .find(|attr| attr.name.local.as_ref() == "id")
+        .map(|attr| attr.value.to_string())
+        .unwrap_or_default()
+}
+
+/// Returns the children of a footnote definition without its numbered label,
+/// which is already part of `[^label]:`.
+fn definition_content(node: &Handle) -> Vec<Handle> {
+    node.children
+        .borrow()
+        .iter()
+        .filter(|child| match &child.data {
+            NodeData::Element { attrs, .. } => {
+                !has_class(&attrs.borrow(), "footnote-definition-label")
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+/// Finds the label of a footnote reference from the `href="#label"` of its link.
+fn reference_label(node: &Handle) -> Option<String> {
+    for child in node.children.borrow().iter() {
+        if let NodeData::Element { name, attrs, .. } = &child.data {
+            if name.local.as_ref() == "a" {
+                return attrs
+                    .borrow()
+                    .iter()
+                    .find(|attr| attr.name.local.as_ref() == "href")
+                    .and_then(|href| href.value.strip_prefix('#').map(str::to_string));
+            }
+        }
+    }
+    None
+}
@@ -1,69 +1,160 @@
+use super::{collect_text, longest_run};
+use crate::ast::{Block, Inline, Node};
+use crate::ConversionContext;
 use crate::ElementHandler;
 // use html5ever::Attribute;
-use markup5ever_rcdom::Handle;
+use markup5ever_rcdom::{Handle, NodeData};
 use crate::MarkdownConverter;
+use crate::StyleParser;
 
 pub struct MarkHandler;
 impl ElementHandler for MarkHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        output.push_str("==");
-        converter.walk_children(node, output, depth);
-        output.push_str("==");
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        lower_inline(converter, node, ctx, Inline::Highlight)
     }
 }
 
 pub struct UnderlineHandler;
 impl ElementHandler for UnderlineHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        output.push_str("<u>");
-        converter.walk_children(node, output, depth);
-        output.push_str("</u>");
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        lower_inline(converter, node, ctx, Inline::Underline)
     }
 }
 
 pub struct EmphasisHandler;
 impl ElementHandler for EmphasisHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        output.push('*');
-        converter.walk_children(node, output, depth);
-        output.push('*');
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        lower_inline(converter, node, ctx, Inline::Emphasis)
     }
 }
 
+/// Writes `<b>` and `<strong>` as strong emphasis, unless a style sets them
+/// in a normal weight. Google Docs wraps everything copied from it in
+/// `<b style="font-weight:normal">`.
 pub struct BoldHandler;
 impl ElementHandler for BoldHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        output.push_str("**");
-        converter.walk_children(node, output, depth);
-        output.push_str("**");
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        if StyleParser::is_normal_weight(attrs) {
+            return converter.lower_children(node, ctx);
+        }
+        lower_inline(converter, node, ctx, Inline::Strong)
     }
 }
 
+/// Writes `<code>` as a code span, with the code taken as raw text.
+///
+/// The delimiter is one backtick longer than any run of backticks in the code,
+/// and it is padded with a space when the code starts or ends with a backtick.
 pub struct InlineCodeHandler;
 impl ElementHandler for InlineCodeHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        output.push('`');
-        converter.walk_children(node, output, depth);
-        output.push('`');
+    fn lower(&self, _converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], _ctx: &mut ConversionContext) -> Vec<Node> {
+        let code = code_span(node);
+        if code.is_empty() {
+            return Vec::new();
+        }
+        vec![Node::Inline(Inline::Code(code))]
     }
 }
 
+/// Returns the code of a `<code>` element as it would be read back from a code span.
+fn code_span(node: &Handle) -> String {
+    let mut code = String::new();
+    collect_text(node, &mut code);
+    // Line endings in a code span are read as spaces
+    code.replace('\n', " ")
+}
+
+/// Writes a code span, with delimiters that can't be mistaken for the code.
+pub(crate) fn write_code_span(output: &mut String, code: &str) {
+    if code.is_empty() {
+        return;
+    }
+
+    let delimiter = "`".repeat(longest_run(code, '`') + 1);
+    let padded = code.starts_with('`')
+        || code.ends_with('`')
+        || (code.starts_with(' ') && code.ends_with(' ') && !code.trim().is_empty());
+    let padding = if padded { " " } else { "" };
+
+    output.push_str(&delimiter);
+    output.push_str(padding);
+    output.push_str(code);
+    output.push_str(padding);
+    output.push_str(&delimiter);
+}
+
 pub struct StrikeThroughHandler;
 impl ElementHandler for StrikeThroughHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        output.push_str("~~");
-        converter.walk_children(node, output, depth);
-        output.push_str("~~");
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        lower_inline(converter, node, ctx, Inline::Strikethrough)
     }
 }
 
+pub struct SuperscriptHandler;
+impl ElementHandler for SuperscriptHandler {
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        lower_inline(converter, node, ctx, Inline::Superscript)
+    }
+}
+
+pub struct SubscriptHandler;
+impl ElementHandler for SubscriptHandler {
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        lower_inline(converter, node, ctx, Inline::Subscript)
+    }
+}
+
+/// Keeps `<kbd>` as HTML, since Markdown has no syntax for keys.
+pub struct KeyboardHandler;
+impl ElementHandler for KeyboardHandler {
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        lower_inline(converter, node, ctx, Inline::Keyboard)
+    }
+}
+
+/// Keeps `<abbr>` as HTML together with its `title`, which holds the expansion.
+pub struct AbbreviationHandler;
+impl ElementHandler for AbbreviationHandler {
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        let title = attrs.iter().find(|attr| attr.name.local.as_ref() == "title").map(|title| title.value.to_string());
+        let children = converter.lower_inlines(node, ctx);
+        vec![Node::Inline(Inline::Abbreviation { title, children })]
+    }
+}
+
+/// Writes `<br>` as a hard line break.
+///
+/// Inside a table cell the break becomes a newline, which the table joins back
+/// up with `<br>`, and inside a heading it becomes a space. A break at the end
+/// of a block isn't shown by the browser, so it is left out.
+pub struct LineBreakHandler;
+impl ElementHandler for LineBreakHandler {
+    fn lower(&self, _converter: &MarkdownConverter, _node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        if ends_block(ctx) {
+            return Vec::new();
+        }
+        vec![Node::Inline(Inline::Break)]
+    }
+}
+
+/// Returns true if only whitespace and other line breaks follow the
+/// handler's element within its parent.
+fn ends_block(ctx: &ConversionContext) -> bool {
+    ctx.next_siblings().iter().all(|child| match &child.data {
+        NodeData::Text { contents } => contents.borrow().trim().is_empty(),
+        NodeData::Element { name, .. } => name.local.as_ref() == "br",
+        _ => true,
+    })
+}
+
 pub struct HorizontalRuleHandler;
 impl ElementHandler for HorizontalRuleHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        output.push('\n');
-        output.push_str("---");
-        converter.walk_children(node, output, depth);
-        // output.push_str("---");
-        output.push('\n');
+    fn lower(&self, _converter: &MarkdownConverter, _node: &Handle, _attrs: &[html5ever::Attribute], _ctx: &mut ConversionContext) -> Vec<Node> {
+        vec![Node::Block(Block::ThematicBreak)]
     }
-}
\ No newline at end of file
+}
+
+/// Lowers an inline element whose children are wrapped in `wrap`.
+fn lower_inline(converter: &MarkdownConverter, node: &Handle, ctx: &mut ConversionContext, wrap: fn(Vec<Inline>) -> Inline) -> Vec<Node> {
+    vec![Node::Inline(wrap(converter.lower_inlines(node, ctx)))]
+}
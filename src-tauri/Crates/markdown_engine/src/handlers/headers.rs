@@ -1,3 +1,6 @@
+use crate::ast::{Block, Node};
+use crate::slug::heading_text;
+use crate::ConversionContext;
 use crate::ElementHandler;
 use crate::MarkdownConverter;
 use html5ever::Attribute;
@@ -14,18 +17,100 @@ impl HeaderHandler {
 }
 
 impl ElementHandler for HeaderHandler {
-    fn handle(
+    fn lower(
         &self,
         converter: &MarkdownConverter,
         node: &Handle,
-        _attrs: &[Attribute],
-        output: &mut String,
-        depth: usize,
-    ) {
-        output.push_str("\n");
-        output.push_str(&"#".repeat(self.level));
-        output.push(' ');
-        converter.walk_children(node, output, depth);
-        // output.push_str("\n");
+        attrs: &[Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let id = heading_id(node, attrs, ctx);
+        let children = converter.lower_inlines(node, ctx);
+        vec![Node::Block(Block::Heading {
+            level: self.level as u8,
+            id,
+            classes: heading_classes(attrs),
+            attributes: heading_attributes(attrs),
+            children,
+        })]
+    }
+}
+
+/// Returns the `id` of a heading if it has to be written as `{#id}`, which
+/// it doesn't when it is the one made from the heading's text.
+fn heading_id(node: &Handle, attrs: &[Attribute], ctx: &mut ConversionContext) -> Option<String> {
+    let id = attrs
+        .iter()
+        .find(|attr| attr.name.local.as_ref() == "id")
+        .map(|attr| attr.value.to_string());
+    ctx.heading_id(id.as_deref(), &heading_text(node))
+}
+
+fn heading_classes(attrs: &[Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|attr| attr.name.local.as_ref() == "class")
+        .flat_map(|attr| attr.value.split_whitespace())
+        .filter(|class| is_attribute_text(class))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Returns the attributes of a heading that were written as `{key=value}`.
+/// Those that the attribute syntax can't hold are left out, as are the ones
+/// the renderer adds itself.
+fn heading_attributes(attrs: &[Attribute]) -> Vec<(String, String)> {
+    attrs
+        .iter()
+        .filter(|attr| !matches!(attr.name.local.as_ref(), "id" | "class" | "data-sourcepos"))
+        .filter(|attr| {
+            let name = attr.name.local.as_ref();
+            is_attribute_text(name)
+                && !name.starts_with(['#', '.'])
+                && !name.contains('=')
+                && (attr.value.is_empty() || is_attribute_text(&attr.value))
+        })
+        .map(|attr| (attr.name.local.to_string(), attr.value.to_string()))
+        .collect()
+}
+
+/// Whether text can be part of the attribute syntax, which ends at
+/// whitespace or braces.
+fn is_attribute_text(text: &str) -> bool {
+    !text.is_empty() && !text.contains(|c: char| c.is_whitespace() || c == '{' || c == '}')
+}
+
+/// Adds the `{#id .class key=value}` after the text of a heading. Without
+/// any, a `}` at the end of the text is escaped so it isn't read as
+/// attributes.
+pub(crate) fn with_attributes(
+    content: &str,
+    id: Option<&str>,
+    classes: &[String],
+    attributes: &[(String, String)],
+) -> String {
+    let content = content.trim_end();
+    let attributes: Vec<String> = id
+        .map(|id| format!("#{}", id))
+        .into_iter()
+        .chain(classes.iter().map(|class| format!(".{}", class)))
+        .chain(
+            attributes
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value)),
+        )
+        .collect();
+    if attributes.is_empty() {
+        return match content.strip_suffix('}') {
+            Some(rest) => format!("{}\\}}", rest),
+            None => content.to_string(),
+        };
+    }
+
+    let attributes = format!("{{{}}}", attributes.join(" "));
+    if content.is_empty() {
+        attributes
+    } else {
+        format!("{} {}", content, attributes)
     }
 }
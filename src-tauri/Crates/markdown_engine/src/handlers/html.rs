@@ -0,0 +1,198 @@
+use super::blocks::{DivHandler, ParagraphHandler};
+use crate::ast::{Block, Inline, Node};
+use crate::ConversionContext;
+use crate::ElementHandler;
+use crate::MarkdownConverter;
+use crate::UnknownElementPolicy;
+use html5ever::serialize::{serialize, SerializeOpts, TraversalScope};
+use html5ever::Attribute;
+use markup5ever_rcdom::{Handle, NodeData, SerializableHandle};
+
+/// Keeps an element and everything in it as raw HTML, serialized by html5ever.
+///
+/// Block elements are written on lines of their own without any blank lines,
+/// since a blank line ends an HTML block in Markdown. Inline elements stay in
+/// the surrounding text.
+pub struct RawHtmlHandler;
+impl ElementHandler for RawHtmlHandler {
+    fn lower(
+        &self,
+        _converter: &MarkdownConverter,
+        node: &Handle,
+        _attrs: &[Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let html = match serialize_node(node) {
+            Ok(html) => html,
+            Err(error) => {
+                ctx.fail(error.to_string());
+                return Vec::new();
+            }
+        };
+
+        if ctx.ancestors().next_back().is_some_and(is_block) {
+            vec![Node::Block(Block::Html(block_html(&html)))]
+        } else {
+            vec![Node::Inline(Inline::Html(html))]
+        }
+    }
+}
+
+/// Keeps an HTML comment as it is, since Markdown passes comments through
+/// too. Comments between blocks are blocks of their own.
+pub(crate) fn lower_comment(comment: &str, ctx: &ConversionContext) -> Node {
+    let html = format!("<!--{}-->", comment);
+    let between_blocks = ctx
+        .ancestors()
+        .next_back()
+        .is_none_or(|tag| matches!(tag, "html" | "body" | "blockquote") || is_block(tag));
+    if between_blocks {
+        Node::Block(Block::Html(html))
+    } else {
+        Node::Inline(Inline::Html(html))
+    }
+}
+
+/// Keeps `<div align>` and `<p align>` as raw HTML, as Markdown has no way to
+/// align a block, unless the unknown element policy strips all HTML. Then
+/// they are converted like any other div or paragraph.
+pub struct AlignedBlockHandler;
+impl ElementHandler for AlignedBlockHandler {
+    fn lower(
+        &self,
+        converter: &MarkdownConverter,
+        node: &Handle,
+        attrs: &[Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let NodeData::Element { name, .. } = &node.data else {
+            return Vec::new();
+        };
+        let tag = name.local.as_ref();
+        if converter.options().unknown_elements != UnknownElementPolicy::Strip {
+            RawHtmlHandler.lower(converter, node, attrs, ctx)
+        } else if tag == "p" {
+            ParagraphHandler.lower(converter, node, attrs, ctx)
+        } else {
+            DivHandler.lower(converter, node, attrs, ctx)
+        }
+    }
+}
+
+/// Serializes an element and everything in it back to HTML.
+fn serialize_node(node: &Handle) -> std::io::Result<String> {
+    let mut html = Vec::new();
+    let opts = SerializeOpts {
+        traversal_scope: TraversalScope::IncludeNode,
+        ..Default::default()
+    };
+    let handle = SerializableHandle::from(node.clone());
+    serialize(&mut html, &handle, opts)?;
+    Ok(String::from_utf8_lossy(&html).into_owned())
+}
+
+/// Drops the blank lines from HTML, which would end the HTML block. Blank
+/// lines of preformatted text are part of the text, so the line break in
+/// front of them is written as `&#10;` instead.
+fn block_html(html: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut in_pre = false;
+    for line in html.lines() {
+        if line.trim().is_empty() {
+            if let Some(last) = lines.last_mut().filter(|_| in_pre) {
+                last.push_str("&#10;");
+                last.push_str(line);
+            }
+            continue;
+        }
+
+        // The last `<pre>` or `</pre>` on the line decides what comes next
+        let open = ["<pre>", "<pre "]
+            .iter()
+            .filter_map(|tag| line.rfind(tag))
+            .max();
+        match (open, line.rfind("</pre>")) {
+            (Some(open), Some(close)) => in_pre = open > close,
+            (Some(_), None) => in_pre = true,
+            (None, Some(_)) => in_pre = false,
+            (None, None) => {}
+        }
+        lines.push(line.to_string());
+    }
+    lines.join("\n")
+}
+
+/// Returns true for elements that start an HTML block in CommonMark, plus a
+/// few others that are laid out as blocks.
+fn is_block(tag: &str) -> bool {
+    matches!(
+        tag,
+        "address"
+            | "article"
+            | "aside"
+            | "audio"
+            | "canvas"
+            | "center"
+            | "details"
+            | "dialog"
+            | "dir"
+            | "div"
+            | "dl"
+            | "fieldset"
+            | "figcaption"
+            | "figure"
+            | "footer"
+            | "form"
+            | "frameset"
+            | "header"
+            | "hgroup"
+            | "iframe"
+            | "legend"
+            | "main"
+            | "menu"
+            | "nav"
+            | "noscript"
+            | "object"
+            | "optgroup"
+            | "option"
+            | "picture"
+            | "section"
+            | "summary"
+            | "svg"
+            | "video"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConverterOptions;
+
+    #[test]
+    fn keeps_blank_lines_in_preformatted_text() {
+        assert_eq!(
+            block_html("<div>\n\n<p>a</p>\n</div>"),
+            "<div>\n<p>a</p>\n</div>"
+        );
+        assert_eq!(
+            block_html("<div><pre>one\n\n\ntwo</pre>\n\n</div>"),
+            "<div><pre>one&#10;&#10;\ntwo</pre>\n</div>"
+        );
+        assert_eq!(
+            block_html("<pre class=\"x\">a\n\nb</pre><pre>c</pre>\n\nd"),
+            "<pre class=\"x\">a&#10;\nb</pre><pre>c</pre>\nd"
+        );
+    }
+
+    #[test]
+    fn keeps_aligned_blocks_unless_stripping_html() {
+        let html = "<p align=\"center\">Centered</p>";
+        let editor = MarkdownConverter::with_options(ConverterOptions::editor());
+        assert_eq!(editor.convert_to_markdown(html), html);
+        assert_eq!(
+            MarkdownConverter::new().convert_to_markdown(html),
+            "Centered"
+        );
+        assert_eq!(editor.convert_to_markdown("<div>Plain</div>"), "Plain");
+    }
+}
@@ -0,0 +1,146 @@
+use super::{escape_attribute, write_destination};
+use crate::ast::{Inline, Node};
+use crate::extensions::images::split_image_size;
+use crate::ConversionContext;
+use crate::ConvertWarning;
+use crate::ElementHandler;
+use crate::MarkdownConverter;
+use html5ever::Attribute;
+use markup5ever_rcdom::Handle;
+
+/// Writes `<img>` elements as `![alt](src "title")`.
+///
+/// Sized images use the Obsidian `![alt|width](src)` or `![alt|widthxheight](src)`
+/// form. Sizes that form can't carry (a height on its own or percentages) are
+/// kept as a raw `<img>` tag instead.
+pub struct ImageHandler;
+impl ElementHandler for ImageHandler {
+    fn lower(
+        &self,
+        _converter: &MarkdownConverter,
+        _node: &Handle,
+        attrs: &[Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let attr = |name: &str| image_attribute(attrs, name);
+        let Some(src) = attr("src") else {
+            ctx.warn(ConvertWarning::DroppedElement {
+                tag: "img".to_string(),
+            });
+            return Vec::new();
+        };
+
+        vec![Node::Inline(Inline::Image {
+            url: src,
+            alt: attr("alt").unwrap_or_default(),
+            title: attr("title"),
+            width: attr("width"),
+            height: attr("height"),
+        })]
+    }
+}
+
+/// Returns the value of an attribute of an image, if it isn't blank.
+fn image_attribute(attrs: &[Attribute], name: &str) -> Option<String> {
+    attrs
+        .iter()
+        .find(|attr| attr.name.local.as_ref() == name)
+        .map(|attr| attr.value.to_string())
+        .filter(|value| !value.trim().is_empty())
+}
+
+/// Writes an image in the shortest form that keeps its size.
+pub(crate) fn write_image_element(
+    output: &mut String,
+    src: &str,
+    alt: Option<String>,
+    title: Option<String>,
+    width: Option<String>,
+    height: Option<String>,
+) {
+    if width.is_none() && height.is_none() {
+        let mut alt = alt.unwrap_or_default();
+        // An alt text ending in `|300` would be read back as a size
+        if let Some((before, _)) = split_image_size(&alt) {
+            alt.insert(before.len(), '\\');
+        }
+        write_image(output, &alt, src, title.as_deref());
+        return;
+    }
+
+    let pixels_width = width.as_deref().and_then(parse_pixels);
+    let pixels_height = height.as_deref().and_then(parse_pixels);
+    let fits_obsidian_size =
+        pixels_width.is_some() && (height.is_none() || pixels_height.is_some());
+
+    if fits_obsidian_size {
+        let mut size = pixels_width.unwrap_or_default().to_string();
+        if let Some(height) = pixels_height {
+            size.push_str(&format!("x{}", height));
+        }
+        let alt = format!("{}|{}", alt.unwrap_or_default(), size);
+        write_image(output, &alt, src, title.as_deref());
+    } else {
+        output.push_str("<img");
+        for (name, value) in [
+            ("src", Some(src.to_string())),
+            ("alt", alt),
+            ("title", title),
+            ("width", width),
+            ("height", height),
+        ] {
+            if let Some(value) = value {
+                output.push_str(&format!(" {}=\"{}\"", name, escape_attribute(&value)));
+            }
+        }
+        output.push('>');
+    }
+}
+
+fn write_image(output: &mut String, alt: &str, src: &str, title: Option<&str>) {
+    output.push_str("![");
+    output.push_str(&escape_brackets(alt));
+    output.push_str("](");
+    write_destination(output, src, title);
+    output.push(')');
+}
+
+/// Escapes the brackets of the alt text that aren't balanced. Balanced ones
+/// are part of the image description, such as a `[[wikilink]]` in it.
+fn escape_brackets(alt: &str) -> String {
+    let mut unbalanced = Vec::new();
+    let mut open = Vec::new();
+    for (index, c) in alt.char_indices() {
+        match c {
+            '[' => open.push(index),
+            ']' => match open.pop() {
+                Some(_) => {}
+                None => unbalanced.push(index),
+            },
+            _ => {}
+        }
+    }
+    unbalanced.extend(open);
+    unbalanced.sort_unstable();
+
+    let mut escaped = String::with_capacity(alt.len());
+    let mut last = 0;
+    for index in unbalanced {
+        escaped.push_str(&alt[last..index]);
+        escaped.push('\\');
+        last = index;
+    }
+    escaped.push_str(&alt[last..]);
+    escaped
+}
+
+/// Parses sizes such as `300` or `300px` into a number of pixels.
+fn parse_pixels(value: &str) -> Option<u32> {
+    let value = value.trim();
+    value
+        .strip_suffix("px")
+        .unwrap_or(value)
+        .trim()
+        .parse()
+        .ok()
+}
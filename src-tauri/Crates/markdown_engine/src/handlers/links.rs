@@ -0,0 +1,94 @@
+use super::write_destination;
+use crate::ast::{Inline, Node};
+use crate::ConversionContext;
+use crate::ElementHandler;
+use crate::LinkStyle;
+use crate::MarkdownConverter;
+use html5ever::Attribute;
+use markup5ever_rcdom::Handle;
+
+/// Writes `<a>` elements as links.
+///
+/// Links whose text is their own URL become autolinks (`<https://...>`).
+/// Links carrying their reference label in `data-reference` are written as
+/// `[text][label]`, and with `LinkStyle::Reference` all other links get a
+/// numbered label. The definitions for those labels are collected in the
+/// context and written at the end of the document.
+pub struct LinkHandler;
+impl ElementHandler for LinkHandler {
+    fn lower(
+        &self,
+        converter: &MarkdownConverter,
+        node: &Handle,
+        attrs: &[Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let attr = |name: &str| {
+            attrs
+                .iter()
+                .find(|attr| attr.name.local.as_ref() == name)
+                .map(|attr| attr.value.to_string())
+        };
+
+        vec![Node::Inline(Inline::Link {
+            url: attr("href").unwrap_or_default(),
+            title: attr("title").filter(|title| !title.is_empty()),
+            reference: attr("data-reference").filter(|label| !label.trim().is_empty()),
+            children: converter.lower_inlines(node, ctx),
+        })]
+    }
+}
+
+/// Finishes a link whose `[text]` was written from `start` on, with either
+/// its reference label or its inline destination.
+pub(crate) fn write_link_target(
+    link_style: LinkStyle,
+    output: &mut String,
+    start: usize,
+    href: &str,
+    title: Option<&str>,
+    reference: Option<&str>,
+    ctx: &mut ConversionContext,
+) {
+    let label = match reference.filter(|label| !label.trim().is_empty()) {
+        Some(label) if ctx.define_link(label, href, title) => Some(label.to_string()),
+        _ if link_style == LinkStyle::Reference => Some(ctx.link_reference(href, title)),
+        _ => None,
+    };
+
+    match label {
+        // `[label][]` is enough when the text is the label itself
+        Some(label) if output[start + 1..output.len() - 1].eq_ignore_ascii_case(&label) => {
+            output.push_str("[]");
+        }
+        Some(label) => {
+            output.push('[');
+            output.push_str(&label);
+            output.push(']');
+        }
+        None => {
+            output.push('(');
+            write_destination(output, href, title);
+            output.push(')');
+        }
+    }
+}
+
+/// Returns the text of an autolink for the link, if its text is the URL
+/// itself or the address of a `mailto:` link.
+pub(crate) fn autolink(text: &str, href: &str) -> Option<String> {
+    let is_url = href.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() >= 2
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+    });
+    let usable = !href.contains(|c: char| c.is_whitespace() || c == '<' || c == '>');
+    if !is_url || !usable {
+        return None;
+    }
+
+    let is_email = href.strip_prefix("mailto:") == Some(text) && text.contains('@');
+    (text == href || is_email).then(|| text.to_string())
+}
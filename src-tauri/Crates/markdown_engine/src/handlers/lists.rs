@@ -1,65 +1,278 @@
+use crate::{ConversionContext, ListContext};
+use crate::ast::{Block, DefinitionItem, Inline, ListItem, Node};
 use crate::ElementHandler;
+use crate::ConvertWarning;
+use crate::handlers::html::RawHtmlHandler;
 // use html5ever::Attribute;
 use markup5ever_rcdom::{NodeData, Handle};
 use crate::MarkdownConverter;
+use std::num::IntErrorKind;
+
+/// The largest number CommonMark allows for an ordered list item, which has
+/// at most nine digits.
+pub(crate) const MAX_LIST_NUMBER: u64 = 999_999_999;
 
 pub struct UnorderedListHandler;
 impl ElementHandler for UnorderedListHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        output.push('\n');
-        converter.walk_children(node, output, depth);
-        output.push('\n');
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        lower_list(converter, node, ctx, false, 1)
     }
 }
 
 pub struct OrderedListHandler;
 impl ElementHandler for OrderedListHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        output.push('\n');
-        converter.walk_children(node, output, depth);
-        output.push('\n');
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        let start = list_number(attrs, "start").unwrap_or(1);
+        lower_list(converter, node, ctx, true, start)
+    }
+}
+
+/// Reads a `start` or `value` attribute, keeping it to a number that can be
+/// written as a list marker.
+fn list_number(attrs: &[html5ever::Attribute], name: &str) -> Option<u64> {
+    let value = &attrs.iter().find(|attr| attr.name.local.as_ref() == name)?.value;
+    match value.trim().parse::<u64>() {
+        Ok(number) => Some(number.min(MAX_LIST_NUMBER)),
+        Err(error) if *error.kind() == IntErrorKind::PosOverflow => Some(MAX_LIST_NUMBER),
+        Err(_) => None,
+    }
+}
+
+/// Gathers the items of a list. Each `<li>` lowers to a list of its own,
+/// which is merged in here; anything else in the list is added to the item
+/// before it. The list is numbered from its first item, which may have a
+/// `value` of its own.
+fn lower_list(converter: &MarkdownConverter, node: &Handle, ctx: &mut ConversionContext, ordered: bool, start: u64) -> Vec<Node> {
+    ctx.push_list(ListContext { ordered, next_number: start });
+    let children = converter.lower_children(node, ctx);
+    ctx.pop_list();
+
+    let mut start = start;
+    let mut items: Vec<ListItem> = Vec::new();
+    for child in children {
+        let block = match child {
+            Node::Inline(Inline::Text(text)) if text.trim().is_empty() => continue,
+            Node::Inline(inline) => Block::Paragraph(vec![inline]),
+            Node::Block(block) => match item_list(block) {
+                Ok((number, child_items)) => {
+                    if items.is_empty() {
+                        start = number;
+                    }
+                    items.extend(child_items);
+                    continue;
+                }
+                Err(block) => block,
+            },
+        };
+        match items.last_mut() {
+            Some(item) => item.children.push(block),
+            None => items.push(ListItem { checked: None, children: vec![block] }),
+        }
+    }
+    vec![Node::Block(Block::List { ordered, start, items })]
+}
+
+/// Returns the number and items of a list lowered from an `<li>`, or gives
+/// back a block that isn't one. For a source map the `<li>` comes in a
+/// `Block::Source`, which is moved inside the item.
+fn item_list(block: Block) -> Result<(u64, Vec<ListItem>), Block> {
+    match block {
+        Block::List { start, items, .. } => Ok((start, items)),
+        Block::Source { path, children } => match <[Block; 1]>::try_from(children) {
+            Ok([Block::List { start, mut items, .. }]) => {
+                if let [item] = items.as_mut_slice() {
+                    let children = std::mem::take(&mut item.children);
+                    item.children = vec![Block::Source { path, children }];
+                }
+                Ok((start, items))
+            }
+            Ok([block]) => Err(Block::Source { path, children: vec![block] }),
+            Err(children) => Err(Block::Source { path, children }),
+        },
+        block => Err(block),
     }
 }
 
 pub struct ListItemHandler;
 impl ElementHandler for ListItemHandler {
-    fn handle(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], output: &mut String, depth: usize) {
-        // Check if parent is ordered or unordered list
-        let (is_ordered, list_index) = if let Some(parent) = node.parent.take() {
-            let parent = parent.upgrade().unwrap();
-            // Count previous list item siblings
-            let list_index = parent.children.borrow()
-                .iter()
-                .take_while(|n| !std::ptr::eq(*n, node))
-                .filter(|n| {
-                    if let NodeData::Element { name, .. } = &n.data {
-                        name.local.as_ref() == "li"
-                    } else {
-                        false
-                    }
-                })
-                .count();
-            
-            if let NodeData::Element { name, .. } = &parent.data {
-                (name.local.as_ref() == "ol", list_index + 1)
-            } else {
-                (false, 0)
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        // Number the item in the innermost list, honouring an explicit `value`
+        let number = match ctx.current_list() {
+            Some(list) => {
+                if let Some(value) = list_number(attrs, "value") {
+                    list.next_number = value;
+                }
+                let number = list.next_number;
+                list.next_number = (number + 1).min(MAX_LIST_NUMBER);
+                number
             }
-        } else {
-            (false, 0)
+            None => 1,
         };
 
-        // Add indentation based on depth
-        output.push_str(&"  ".repeat(depth.saturating_sub(4)));
-        
-        // Add the appropriate list marker
-        if is_ordered {
-            output.push_str(&format!("{}. ", list_index));
+        let item = ListItem { checked: task_state(node, attrs), children: converter.lower_blocks(node, ctx) };
+        vec![Node::Block(Block::List { ordered: false, start: number, items: vec![item] })]
+    }
+}
+
+/// Checkbox inputs only ever appear in task list items, where ListItemHandler
+/// has already written their state, so inputs produce no output of their own.
+pub struct InputHandler;
+impl ElementHandler for InputHandler {
+    fn lower(&self, _converter: &MarkdownConverter, _node: &Handle, _attrs: &[html5ever::Attribute], _ctx: &mut ConversionContext) -> Vec<Node> {
+        Vec::new()
+    }
+}
+
+/// The editor puts the checkbox of a task item in a `<label>`, which goes
+/// with the checkbox. Any other label is treated like an element without a
+/// handler.
+pub struct LabelHandler;
+impl ElementHandler for LabelHandler {
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        if ctx.parent() == Some("li") && find_checkbox(node).is_some() {
+            Vec::new()
+        } else if converter.options().keeps_html("label") {
+            RawHtmlHandler.lower(converter, node, attrs, ctx)
         } else {
-            output.push_str("- ");
+            ctx.warn(ConvertWarning::StrippedElement { tag: "label".to_string() });
+            converter.lower_children(node, ctx)
         }
-        
-        converter.walk_children(node, output, depth);
-        output.push('\n');
     }
-}
\ No newline at end of file
+}
+
+/// Writes `<dl>` as a definition list, with each group of terms and their
+/// definitions separated by a blank line:
+///
+/// ```text
+/// Term
+/// :   Definition
+/// ```
+pub struct DefinitionListHandler;
+impl ElementHandler for DefinitionListHandler {
+    /// Terms and definitions lower to lists of their own, which are merged
+    /// into groups here.
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        let mut items: Vec<DefinitionItem> = Vec::new();
+        for child in converter.lower_children(node, ctx) {
+            let Node::Block(block) = child else {
+                continue;
+            };
+            let Some(child_items) = definition_items(block) else {
+                continue;
+            };
+            for child_item in child_items {
+                match items.last_mut() {
+                    // A term after a definition starts the next group
+                    Some(item) if child_item.terms.is_empty() || item.definitions.is_empty() => {
+                        item.terms.extend(child_item.terms);
+                        item.definitions.extend(child_item.definitions);
+                    }
+                    _ => items.push(child_item),
+                }
+            }
+        }
+        vec![Node::Block(Block::DefinitionList(items))]
+    }
+}
+
+/// Returns the groups lowered from a `<dt>` or `<dd>`. For a source map the
+/// element comes in a `Block::Source`, which is moved inside the term or
+/// definition.
+fn definition_items(block: Block) -> Option<Vec<DefinitionItem>> {
+    match block {
+        Block::DefinitionList(items) => Some(items),
+        Block::Source { path, children } => match <[Block; 1]>::try_from(children) {
+            Ok([Block::DefinitionList(mut items)]) => {
+                if let [item] = items.as_mut_slice() {
+                    match (item.terms.as_mut_slice(), item.definitions.as_mut_slice()) {
+                        ([term], []) => {
+                            let children = std::mem::take(term);
+                            *term = vec![Inline::Source { path, children }];
+                        }
+                        ([], [definition]) => {
+                            let children = std::mem::take(definition);
+                            *definition = vec![Block::Source { path, children }];
+                        }
+                        _ => {}
+                    }
+                }
+                Some(items)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Writes a `<dt>` as a term on a line of its own.
+pub struct DefinitionTermHandler;
+impl ElementHandler for DefinitionTermHandler {
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        let item = DefinitionItem { terms: vec![converter.lower_inlines(node, ctx)], definitions: Vec::new() };
+        vec![Node::Block(Block::DefinitionList(vec![item]))]
+    }
+}
+
+/// Writes a `<dd>` as `:   definition`, with the following lines indented to
+/// line up with the first.
+pub struct DefinitionHandler;
+impl ElementHandler for DefinitionHandler {
+    fn lower(&self, converter: &MarkdownConverter, node: &Handle, _attrs: &[html5ever::Attribute], ctx: &mut ConversionContext) -> Vec<Node> {
+        let item = DefinitionItem { terms: Vec::new(), definitions: vec![converter.lower_blocks(node, ctx)] };
+        vec![Node::Block(Block::DefinitionList(vec![item]))]
+    }
+}
+
+/// Returns whether a list item is a checked (`Some(true)`) or unchecked
+/// (`Some(false)`) task, or `None` for a plain list item.
+///
+/// Both pulldown-cmark's `<input type="checkbox" checked>` and the editor's
+/// `data-type="taskItem"` / `data-checked` markup are recognised.
+fn task_state(node: &Handle, attrs: &[html5ever::Attribute]) -> Option<bool> {
+    let attr = |name: &str| {
+        attrs
+            .iter()
+            .find(|attr| attr.name.local.as_ref() == name)
+            .map(|attr| attr.value.to_string())
+    };
+
+    if let Some(checked) = attr("data-checked") {
+        return Some(checked == "true");
+    }
+
+    let is_task_item = attr("data-type").is_some_and(|kind| kind == "taskItem");
+    match find_checkbox(node) {
+        Some(checked) => Some(checked),
+        None if is_task_item => Some(false),
+        None => None,
+    }
+}
+
+/// Looks for the checkbox of a list item, without descending into nested lists.
+fn find_checkbox(node: &Handle) -> Option<bool> {
+    // Walked without recursion, in document order, as nothing limits how deep
+    // the item goes
+    let mut pending: Vec<Handle> = node.children.borrow().iter().rev().cloned().collect();
+    while let Some(child) = pending.pop() {
+        let NodeData::Element { name, attrs, .. } = &child.data else {
+            continue;
+        };
+        match name.local.as_ref() {
+            "ul" | "ol" => {}
+            "input" => {
+                let attrs = attrs.borrow();
+                let is_checkbox = attrs.iter().any(|attr| {
+                    attr.name.local.as_ref() == "type"
+                        && attr.value.eq_ignore_ascii_case("checkbox")
+                });
+                if is_checkbox {
+                    return Some(attrs.iter().any(|attr| {
+                        attr.name.local.as_ref() == "checked" && attr.value.as_ref() != "false"
+                    }));
+                }
+            }
+            _ => pending.extend(child.children.borrow().iter().rev().cloned()),
+        }
+    }
+    None
+}
@@ -0,0 +1,64 @@
+use super::collect_text;
+use crate::ast::{Block, Inline, Node};
+use crate::ConversionContext;
+use crate::ElementHandler;
+use crate::MarkdownConverter;
+use html5ever::Attribute;
+use markup5ever_rcdom::{Handle, NodeData};
+
+/// Writes `span.math-inline` and `div.math-display` elements back as `$tex$`
+/// and `$$tex$$`. A `div` is display math on its own, while display math
+/// inside a paragraph comes as a `span.math-display`.
+///
+/// The TeX source is taken from a `data-latex` attribute when the editor keeps
+/// it there, or else from the element's text. Either way it is written exactly
+/// as it was, without whitespace collapsing or escaping.
+pub struct MathHandler {
+    display: bool,
+}
+
+impl MathHandler {
+    pub fn inline() -> Self {
+        Self { display: false }
+    }
+
+    pub fn display() -> Self {
+        Self { display: true }
+    }
+}
+
+impl ElementHandler for MathHandler {
+    fn lower(
+        &self,
+        _converter: &MarkdownConverter,
+        node: &Handle,
+        attrs: &[Attribute],
+        _ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let tex = math_source(node, attrs);
+        if self.display && is_block(node) {
+            vec![Node::Block(Block::Math(tex))]
+        } else {
+            vec![Node::Inline(Inline::Math {
+                tex,
+                display: self.display,
+            })]
+        }
+    }
+}
+
+fn math_source(node: &Handle, attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .find(|attr| attr.name.local.as_ref() == "data-latex")
+        .map(|attr| attr.value.to_string())
+        .unwrap_or_else(|| {
+            let mut tex = String::new();
+            collect_text(node, &mut tex);
+            tex
+        })
+}
+
+fn is_block(node: &Handle) -> bool {
+    matches!(&node.data, NodeData::Element { name, .. } if name.local.as_ref() == "div")
+}
@@ -1,6 +1,163 @@
 //! This module has all the handlers required by the markdown converter.
 
 pub mod headers;
+pub mod html;
 pub mod lists;
+pub mod math;
 pub mod blocks;
-pub mod formatting;
\ No newline at end of file
+pub mod callouts;
+pub mod footnotes;
+pub mod formatting;
+pub mod images;
+pub mod links;
+pub mod tables;
+pub mod wikilinks;
+
+use html5ever::Attribute;
+use markup5ever_rcdom::{Handle, NodeData};
+use std::borrow::Cow;
+
+/// Returns true if the `class` attribute contains the given class name.
+pub(crate) fn has_class(attrs: &[Attribute], class: &str) -> bool {
+    attrs.iter().any(|attr| {
+        attr.name.local.as_ref() == "class" && attr.value.split_whitespace().any(|c| c == class)
+    })
+}
+
+/// Escapes a value for use inside a double-quoted HTML attribute.
+pub(crate) fn escape_attribute(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+/// Writes the destination and optional title of a link or image, as in
+/// `[text](url "title")` or `[label]: url "title"`.
+///
+/// The renderer percent-encodes spaces and other characters in destinations,
+/// which are decoded again for paths in the note's folder, so that
+/// `Pasted%20image.png` is written as `<Pasted image.png>`.
+pub(crate) fn write_destination(output: &mut String, url: &str, title: Option<&str>) {
+    let url = if is_relative(url) {
+        decode_path(url)
+    } else {
+        Cow::Borrowed(url)
+    };
+
+    // Destinations with spaces or unbalanced parentheses need the angle bracket form
+    if url.is_empty() || url.contains(char::is_whitespace) || url.contains(['(', ')']) {
+        output.push('<');
+        output.push_str(&url);
+        output.push('>');
+    } else {
+        output.push_str(&url);
+    }
+
+    if let Some(title) = title {
+        output.push_str(" \"");
+        output.push_str(&title.replace('"', "\\\""));
+        output.push('"');
+    }
+}
+
+/// Returns true for destinations without a scheme or host, such as
+/// `image.png` or `../notes/note.md`.
+fn is_relative(url: &str) -> bool {
+    let has_scheme = url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'))
+    });
+    !has_scheme && !url.starts_with("//")
+}
+
+/// Decodes the percent-encoded spaces and non-ASCII characters of a path.
+/// Other escapes are kept, as decoding them could change where the path
+/// leads, and so is the whole path if it doesn't decode to valid UTF-8.
+fn decode_path(url: &str) -> Cow<'_, str> {
+    if !url.contains('%') {
+        return Cow::Borrowed(url);
+    }
+
+    let bytes = url.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = url
+            .get(index + 1..index + 3)
+            .filter(|_| bytes[index] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .filter(|byte| *byte == b' ' || !byte.is_ascii());
+        match byte {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    match String::from_utf8(decoded) {
+        Ok(decoded) => Cow::Owned(decoded),
+        Err(_) => Cow::Borrowed(url),
+    }
+}
+
+/// Appends the text of all descendants of a node, exactly as it is in the DOM.
+pub(crate) fn collect_text(node: &Handle, output: &mut String) {
+    // Walked without recursion, as nothing limits how deep the element goes
+    let mut pending: Vec<Handle> = node.children.borrow().iter().rev().cloned().collect();
+    while let Some(node) = pending.pop() {
+        match &node.data {
+            NodeData::Text { contents } => output.push_str(&contents.borrow()),
+            _ => pending.extend(node.children.borrow().iter().rev().cloned()),
+        }
+    }
+}
+
+/// Returns the length of the longest run of `c` in the text.
+pub(crate) fn longest_run(text: &str, c: char) -> usize {
+    text.split(|other| other != c)
+        .map(|run| run.len() / c.len_utf8())
+        .max()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn destination(url: &str, title: Option<&str>) -> String {
+        let mut output = String::new();
+        write_destination(&mut output, url, title);
+        output
+    }
+
+    #[test]
+    fn decodes_relative_paths() {
+        assert_eq!(
+            destination("Pasted%20image%202024.png", None),
+            "<Pasted image 2024.png>"
+        );
+        assert_eq!(destination("Gr%C3%BC%C3%9Fe.md", None), "Grüße.md");
+        assert_eq!(destination("a%2Fb%25c.png", None), "a%2Fb%25c.png");
+        assert_eq!(destination("broken%C3.png", None), "broken%C3.png");
+        assert_eq!(destination("a%2", None), "a%2");
+    }
+
+    #[test]
+    fn leaves_urls_encoded() {
+        assert_eq!(
+            destination("https://example.com/a%20b", Some("Say \"hi\"")),
+            "https://example.com/a%20b \"Say \\\"hi\\\"\""
+        );
+        assert_eq!(
+            destination("//example.com/a%20b", None),
+            "//example.com/a%20b"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use crate::ast::{Alignment, Block, Inline, Node, TableCell};
+use crate::source_map::node_path;
+use crate::ConversionContext;
+use crate::ElementHandler;
+use crate::MarkdownConverter;
+use crate::StyleParser;
+use html5ever::Attribute;
+use markup5ever_rcdom::{Handle, NodeData};
+use std::ops::Range;
+
+/// Returns the column alignment of a cell, taken from the `align` attribute
+/// or the `text-align` style.
+fn alignment(attrs: &[Attribute]) -> Alignment {
+    for attr in attrs {
+        let value = match attr.name.local.as_ref() {
+            "align" => attr.value.to_string(),
+            "style" => match StyleParser::parse_styles(&attr.value).get("text-align") {
+                Some(value) => value.clone(),
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        match value.trim().to_ascii_lowercase().as_str() {
+            "left" | "start" => return Alignment::Left,
+            "center" => return Alignment::Center,
+            "right" | "end" => return Alignment::Right,
+            _ => {}
+        }
+    }
+    Alignment::None
+}
+
+pub(crate) struct Cell {
+    pub(crate) content: String,
+    pub(crate) alignment: Alignment,
+}
+
+/// Where a row was written by `write_rows`, as the bytes of its line and of
+/// the content of each of its cells.
+pub(crate) struct RowPosition {
+    pub(crate) line: Range<usize>,
+    pub(crate) cells: Vec<Range<usize>>,
+}
+
+/// Writes `<table>` elements as GitHub-flavored pipe tables.
+///
+/// The whole table is collected before anything is written, so that every
+/// column can be padded to the width of its widest cell.
+pub struct TableHandler;
+impl ElementHandler for TableHandler {
+    fn lower(
+        &self,
+        converter: &MarkdownConverter,
+        node: &Handle,
+        _attrs: &[Attribute],
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let rows = table_rows(node)
+            .iter()
+            .map(|row| {
+                let mut cells = Vec::new();
+                for (cell, attrs, span) in row_cells(row) {
+                    let mut children = converter.lower_inlines(&cell, ctx);
+                    if ctx.maps_sources() {
+                        if let Some(path) = node_path(&cell, ctx) {
+                            children = vec![Inline::Source { path, children }];
+                        }
+                    }
+                    cells.push(TableCell {
+                        alignment: alignment(&attrs),
+                        children,
+                    });
+                    for _ in 1..span {
+                        cells.push(TableCell::default());
+                    }
+                }
+                cells
+            })
+            .collect();
+
+        vec![Node::Block(Block::Table(rows))]
+    }
+}
+
+/// Writes the rows of a table, padding every column to the width of its
+/// widest cell. Returns where each row was written.
+pub(crate) fn write_rows(output: &mut String, rows: &[Vec<Cell>]) -> Vec<RowPosition> {
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    if columns == 0 {
+        return Vec::new();
+    }
+
+    // A column takes the alignment of the first cell that declares one,
+    // which is normally the header cell.
+    let alignments: Vec<Alignment> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.alignment)
+                .find(|alignment| *alignment != Alignment::None)
+                .unwrap_or(Alignment::None)
+        })
+        .collect();
+
+    // Delimiter rows need at least three dashes per column.
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.content.chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(3)
+        })
+        .collect();
+
+    let mut positions = Vec::new();
+    output.push('\n');
+    for (index, row) in rows.iter().enumerate() {
+        let line_start = output.len();
+        let mut cells = Vec::new();
+        output.push('|');
+        for column in 0..columns {
+            let content = row.get(column).map_or("", |cell| cell.content.as_str());
+            let padded = pad(content, widths[column], alignments[column]);
+            output.push(' ');
+            if column < row.len() {
+                // Cell content never starts with a space, so what is in front is padding
+                let padding = padded.len() - padded.trim_start_matches(' ').len();
+                let start = output.len() + if content.is_empty() { 0 } else { padding };
+                cells.push(start..start + content.len());
+            }
+            output.push_str(&padded);
+            output.push_str(" |");
+        }
+        positions.push(RowPosition {
+            line: line_start..output.len(),
+            cells,
+        });
+        output.push('\n');
+
+        // GFM tables always treat the first row as the header.
+        if index == 0 {
+            output.push('|');
+            for column in 0..columns {
+                output.push(' ');
+                output.push_str(&delimiter(widths[column], alignments[column]));
+                output.push_str(" |");
+            }
+            output.push('\n');
+        }
+    }
+    positions
+}
+
+/// Returns the rows of a table, looking through `thead`, `tbody` and `tfoot`
+/// sections in document order.
+fn table_rows(node: &Handle) -> Vec<Handle> {
+    let mut rows = Vec::new();
+    for child in node.children.borrow().iter() {
+        if let NodeData::Element { name, .. } = &child.data {
+            match name.local.as_ref() {
+                "thead" | "tbody" | "tfoot" => rows.extend(table_rows(child)),
+                "tr" => rows.push(child.clone()),
+                _ => {}
+            }
+        }
+    }
+    rows
+}
+
+/// Returns the cells of a row with their attributes and the number of
+/// columns they span, which browsers limit to 1000.
+fn row_cells(row: &Handle) -> Vec<(Handle, Vec<Attribute>, usize)> {
+    let mut cells = Vec::new();
+    for child in row.children.borrow().iter() {
+        if let NodeData::Element { name, attrs, .. } = &child.data {
+            if !matches!(name.local.as_ref(), "th" | "td") {
+                continue;
+            }
+
+            let attrs = attrs.borrow().clone();
+            let span = attrs
+                .iter()
+                .find(|attr| attr.name.local.as_ref() == "colspan")
+                .and_then(|attr| attr.value.trim().parse::<usize>().ok())
+                .unwrap_or(1)
+                .min(1000);
+            cells.push((child.clone(), attrs, span));
+        }
+    }
+    cells
+}
+
+/// Flattens cell content onto a single line and escapes the pipes in it.
+pub(crate) fn format_cell(content: &str) -> String {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("<br>")
+        .replace('|', "\\|")
+}
+
+fn pad(content: &str, width: usize, alignment: Alignment) -> String {
+    let fill = width.saturating_sub(content.chars().count());
+    match alignment {
+        Alignment::Right => format!("{}{}", " ".repeat(fill), content),
+        Alignment::Center => {
+            let left = fill / 2;
+            format!("{}{}{}", " ".repeat(left), content, " ".repeat(fill - left))
+        }
+        Alignment::None | Alignment::Left => format!("{}{}", content, " ".repeat(fill)),
+    }
+}
+
+fn delimiter(width: usize, alignment: Alignment) -> String {
+    match alignment {
+        Alignment::None => "-".repeat(width),
+        Alignment::Left => format!(":{}", "-".repeat(width - 1)),
+        Alignment::Right => format!("{}:", "-".repeat(width - 1)),
+        Alignment::Center => format!(":{}:", "-".repeat(width - 2)),
+    }
+}
@@ -0,0 +1,51 @@
+use super::collect_text;
+use crate::ast::{Inline, Node};
+use crate::ConversionContext;
+use crate::ElementHandler;
+use crate::MarkdownConverter;
+use html5ever::Attribute;
+use markup5ever_rcdom::Handle;
+
+/// Writes `a.wikilink` elements back as `[[Note]]`, `[[Note#Heading]]` or
+/// `[[Note|alias]]`.
+///
+/// The note and heading come from the `data-target` and `data-heading`
+/// attributes, falling back to the `href`. The link text is written as an
+/// alias whenever it differs from the target, so a link renamed in the editor
+/// keeps its new text.
+pub struct WikiLinkHandler;
+impl ElementHandler for WikiLinkHandler {
+    fn lower(
+        &self,
+        _converter: &MarkdownConverter,
+        node: &Handle,
+        attrs: &[Attribute],
+        _ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let (target, alias) = wikilink(node, attrs);
+        vec![Node::Inline(Inline::WikiLink { target, alias })]
+    }
+}
+
+/// Returns the target of a wikilink and its alias, if it has one.
+fn wikilink(node: &Handle, attrs: &[Attribute]) -> (String, Option<String>) {
+    let attr = |name: &str| {
+        attrs
+            .iter()
+            .find(|attr| attr.name.local.as_ref() == name)
+            .map(|attr| attr.value.to_string())
+    };
+
+    let target = match attr("data-target") {
+        Some(note) => match attr("data-heading") {
+            Some(heading) => format!("{}#{}", note, heading),
+            None => note,
+        },
+        None => attr("href").unwrap_or_default(),
+    };
+
+    let mut text = String::new();
+    collect_text(node, &mut text);
+    let alias = (!text.is_empty() && text != target).then_some(text);
+    (target, alias)
+}
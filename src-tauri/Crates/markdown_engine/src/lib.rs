@@ -23,108 +23,390 @@ use markup5ever_rcdom::{Handle, NodeData, RcDom};
 // use std::borrow::Cow;
 use std::collections::HashMap;
 
+pub mod ast;
+mod context;
+mod error;
+mod escape;
+mod extensions;
+mod frontmatter;
 mod handlers;
+mod matcher;
+mod options;
+mod renderer;
+mod serializer;
+mod slug;
+mod source_map;
+#[cfg(test)]
+mod tests;
+mod whitespace;
+mod wrap;
 
+pub use context::{ConversionContext, LinkDefinition, ListContext};
+pub use error::{Conversion, ConvertError, ConvertWarning};
+pub use extensions::{
+    CalloutExtension, HighlightExtension, ImageSizeExtension, MathExtension,
+    ReferenceLinkExtension, TagExtension, WikiLinkExtension,
+};
+pub use frontmatter::{join_frontmatter, split_frontmatter, FrontmatterBlock};
+pub use matcher::ElementMatcher;
+pub use options::{
+    ConverterOptions, HeadingStyle, LineBreakStyle, LinkStyle, ScriptStyle, UnderlineStyle,
+    UnknownElementPolicy,
+};
+pub use pulldown_cmark;
+pub use renderer::{HtmlRenderer, InlineExtension, RenderExtension, SourceEvent};
+pub use source_map::NodeSpan;
+
+use ast::{Block, Document, Inline, Node};
 use handlers::blocks::{BlockquoteHandler, CodeBlockHandler, DivHandler, ParagraphHandler};
+use handlers::callouts::CalloutHandler;
+use handlers::footnotes::{FootnoteDefinitionHandler, FootnoteReferenceHandler};
 use handlers::formatting::{
-    BoldHandler, EmphasisHandler, HorizontalRuleHandler, InlineCodeHandler, MarkHandler,
-    StrikeThroughHandler, UnderlineHandler,
+    AbbreviationHandler, BoldHandler, EmphasisHandler, HorizontalRuleHandler, InlineCodeHandler,
+    KeyboardHandler, LineBreakHandler, MarkHandler, StrikeThroughHandler, SubscriptHandler,
+    SuperscriptHandler, UnderlineHandler,
 };
 use handlers::headers::HeaderHandler;
-use handlers::lists::{ListItemHandler, OrderedListHandler, UnorderedListHandler};
+use handlers::html::{AlignedBlockHandler, RawHtmlHandler};
+use handlers::images::ImageHandler;
+use handlers::links::LinkHandler;
+use handlers::lists::{
+    DefinitionHandler, DefinitionListHandler, DefinitionTermHandler, InputHandler, LabelHandler,
+    ListItemHandler, OrderedListHandler, UnorderedListHandler,
+};
+use handlers::math::MathHandler;
+use handlers::tables::TableHandler;
+use handlers::wikilinks::WikiLinkHandler;
 
 // Public interface
 pub fn convert_to_markdown(html: &str) -> String {
     MarkdownConverter::new().convert_to_markdown(html)
 }
 
+/// Like `convert_to_markdown`, but reports HTML that couldn't be converted.
+pub fn try_convert_to_markdown(html: &str) -> Result<String, ConvertError> {
+    MarkdownConverter::new().try_convert_to_markdown(html)
+}
+
+pub fn convert_to_html(markdown: &str) -> String {
+    HtmlRenderer::new().render(markdown)
+}
+
 // Define element handlers as trait for better extensibility
+//
+// A handler implements one of the two methods: `lower`, which builds nodes of
+// the Markdown tree, or `handle`, which writes Markdown directly and is kept
+// as it is in the tree.
 pub trait ElementHandler {
+    /// Writes the element as Markdown, for handlers that don't lower it. Block
+    /// elements start their output with a newline. Only the default `lower`
+    /// calls this.
     fn handle(
+        &self,
+        _converter: &MarkdownConverter,
+        _node: &Handle,
+        _attrs: &[html5ever::Attribute],
+        _output: &mut String,
+        _ctx: &mut ConversionContext,
+    ) {
+    }
+
+    /// Lowers the element into nodes of the tree built by
+    /// `MarkdownConverter::convert_to_ast`, which is what all conversions
+    /// write out. By default the Markdown written by `handle` is kept as it
+    /// is, as a `Markdown` node.
+    fn lower(
         &self,
         converter: &MarkdownConverter,
         node: &Handle,
         attrs: &[html5ever::Attribute],
-        output: &mut String,
-        depth: usize,
-    );
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let mut output = String::new();
+        self.handle(converter, node, attrs, &mut output, ctx);
+        Node::markdown(output).into_iter().collect()
+    }
 }
 
 // Struct to hold element handlers
 pub struct MarkdownConverter {
-    handlers: HashMap<&'static str, Box<dyn ElementHandler>>,
+    handlers: HashMap<String, Box<dyn ElementHandler>>,
+    // Handlers selected by an ElementMatcher, checked before `handlers`, latest first
+    matched_handlers: Vec<(ElementMatcher, Box<dyn ElementHandler>)>,
+    options: ConverterOptions,
+}
+
+impl Default for MarkdownConverter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MarkdownConverter {
     pub fn new() -> Self {
+        Self::with_options(ConverterOptions::default())
+    }
+
+    /// Creates a converter that writes Markdown in the style set by `options`.
+    pub fn with_options(options: ConverterOptions) -> Self {
         let mut converter = Self {
             handlers: HashMap::new(),
+            matched_handlers: Vec::new(),
+            options,
         };
 
         // Register handlers for different elements
-        converter.handlers.insert("div", Box::new(DivHandler));
-        converter.handlers.insert("p", Box::new(ParagraphHandler));
-        converter.handlers.insert("mark", Box::new(MarkHandler));
         converter
-            .handlers
-            .insert("s", Box::new(StrikeThroughHandler));
-        converter.handlers.insert("em", Box::new(EmphasisHandler));
-        converter.handlers.insert("b", Box::new(BoldHandler));
-        converter.handlers.insert("strong", Box::new(BoldHandler));
-        converter.handlers.insert("u", Box::new(UnderlineHandler));
-        converter.handlers.insert("a", Box::new(LinkHandler));
-        converter.handlers.insert("span", Box::new(SpanHandler));
+            .register_handler("div", DivHandler)
+            .register_handler("p", ParagraphHandler)
+            .register_handler("mark", MarkHandler)
+            .register_handler("s", StrikeThroughHandler)
+            .register_handler("del", StrikeThroughHandler)
+            .register_handler("em", EmphasisHandler)
+            .register_handler("i", EmphasisHandler)
+            .register_handler("b", BoldHandler)
+            .register_handler("strong", BoldHandler)
+            .register_handler("u", UnderlineHandler)
+            .register_handler("a", LinkHandler)
+            .register_handler("img", ImageHandler)
+            .register_handler("span", SpanHandler)
+            .register_handler("blockquote", BlockquoteHandler)
+            .register_handler("code", InlineCodeHandler)
+            .register_handler("pre", CodeBlockHandler)
+            .register_handler("hr", HorizontalRuleHandler)
+            .register_handler("br", LineBreakHandler)
+            .register_handler("sup", SuperscriptHandler)
+            .register_handler("sub", SubscriptHandler)
+            .register_handler("kbd", KeyboardHandler)
+            .register_handler("abbr", AbbreviationHandler);
+
+        // Register handlers for footnotes, which pulldown-cmark marks with classes
         converter
-            .handlers
-            .insert("blockquote", Box::new(BlockquoteHandler));
+            .register_matched_handler(
+                ElementMatcher::tag("sup").with_class("footnote-reference"),
+                FootnoteReferenceHandler,
+            )
+            .register_matched_handler(
+                ElementMatcher::tag("div").with_class("footnote-definition"),
+                FootnoteDefinitionHandler,
+            );
+
+        // Register handlers for callouts, which are written as blockquotes
+        converter.register_matched_handler(
+            ElementMatcher::tag("div").with_class("callout"),
+            CalloutHandler,
+        );
+
+        // Register handlers for wikilinks, which are links marked with a class
+        converter.register_matched_handler(
+            ElementMatcher::tag("a").with_class("wikilink"),
+            WikiLinkHandler,
+        );
+
+        // Register handlers for math, matching both our own classes and
+        // pulldown-cmark's `math math-inline` / `math math-display` spans
         converter
-            .handlers
-            .insert("code", Box::new(InlineCodeHandler));
-        converter.handlers.insert("pre", Box::new(CodeBlockHandler));
+            .register_matched_handler(
+                ElementMatcher::any().with_class("math-inline"),
+                MathHandler::inline(),
+            )
+            .register_matched_handler(
+                ElementMatcher::any().with_class("math-display"),
+                MathHandler::display(),
+            );
+
+        // Register handlers for aligned blocks, which are kept as HTML
         converter
-            .handlers
-            .insert("hr", Box::new(HorizontalRuleHandler));
+            .register_matched_handler(
+                ElementMatcher::tag("div").with_attribute("align", None),
+                AlignedBlockHandler,
+            )
+            .register_matched_handler(
+                ElementMatcher::tag("p").with_attribute("align", None),
+                AlignedBlockHandler,
+            );
 
         // Register handlers for lists
         converter
-            .handlers
-            .insert("ul", Box::new(UnorderedListHandler));
-        converter
-            .handlers
-            .insert("ol", Box::new(OrderedListHandler));
-        converter.handlers.insert("li", Box::new(ListItemHandler));
+            .register_handler("ul", UnorderedListHandler)
+            .register_handler("ol", OrderedListHandler)
+            .register_handler("li", ListItemHandler)
+            .register_handler("input", InputHandler)
+            .register_handler("label", LabelHandler)
+            .register_handler("dl", DefinitionListHandler)
+            .register_handler("dt", DefinitionTermHandler)
+            .register_handler("dd", DefinitionHandler);
+
+        // Register handlers for tables
+        converter.register_handler("table", TableHandler);
 
         // Register handlers for headers
-        converter
-            .handlers
-            .insert("h1", Box::new(HeaderHandler::level(1)));
-        converter
-            .handlers
-            .insert("h2", Box::new(HeaderHandler::level(2)));
-        converter
-            .handlers
-            .insert("h3", Box::new(HeaderHandler::level(3)));
-        converter
-            .handlers
-            .insert("h4", Box::new(HeaderHandler::level(4)));
-        converter
-            .handlers
-            .insert("h5", Box::new(HeaderHandler::level(5)));
-        converter
-            .handlers
-            .insert("h6", Box::new(HeaderHandler::level(6)));
+        for level in 1..=6 {
+            converter.register_handler(&format!("h{}", level), HeaderHandler::level(level));
+        }
 
         converter
     }
 
+    /// Registers `handler` for all elements with the given tag name, replacing
+    /// the handler previously registered for it.
+    pub fn register_handler<H>(&mut self, tag: &str, handler: H) -> &mut Self
+    where
+        H: ElementHandler + 'static,
+    {
+        self.handlers
+            .insert(tag.to_ascii_lowercase(), Box::new(handler));
+        self
+    }
+
+    /// Registers `handler` for the elements selected by `matcher`.
+    ///
+    /// Matched handlers take precedence over handlers registered by tag name,
+    /// and a later matched handler takes precedence over an earlier one.
+    pub fn register_matched_handler<H>(&mut self, matcher: ElementMatcher, handler: H) -> &mut Self
+    where
+        H: ElementHandler + 'static,
+    {
+        self.matched_handlers.push((matcher, Box::new(handler)));
+        self
+    }
+
+    /// Removes the handlers for the given tag name, including matched handlers
+    /// limited to that tag. Elements without a handler have their children
+    /// processed as if the element wasn't there.
+    pub fn remove_handler(&mut self, tag: &str) -> &mut Self {
+        let tag = tag.to_ascii_lowercase();
+        self.handlers.remove(&tag);
+        self.matched_handlers
+            .retain(|(matcher, _)| matcher.tag_name() != Some(tag.as_str()));
+        self
+    }
+
+    /// Converts HTML to Markdown, leaving out whatever couldn't be converted.
+    ///
+    /// This is `serialize(convert_to_ast(html))`, done in one go.
     pub fn convert_to_markdown(&self, html: &str) -> String {
         // println!("{}", html);
         let dom = self.parse_to_dom(html);
-        let mut markdown = String::with_capacity(html.len());
-        self.traverse_dom(&dom.document, &mut markdown, 0);
-        markdown.trim().to_string()
+        let mut ctx = ConversionContext::new();
+        let document = self.lower_document(&dom, &mut ctx);
+        self.write_markdown(&document, &mut ctx).0
+    }
+
+    /// Like `convert_to_markdown`, but also returns the range of the output
+    /// that each block element was written as, e.g. to find the line of the
+    /// paragraph the cursor is in.
+    pub fn convert_with_source_map(&self, html: &str) -> (String, Vec<NodeSpan>) {
+        let dom = self.parse_to_dom(html);
+        let mut ctx = ConversionContext::with_source_map();
+        let document = self.lower_document(&dom, &mut ctx);
+        self.write_markdown(&document, &mut ctx)
+    }
+
+    /// Converts HTML to Markdown, failing instead of writing a document that
+    /// is missing parts of the HTML.
+    pub fn try_convert_to_markdown(&self, html: &str) -> Result<String, ConvertError> {
+        self.try_convert_with_warnings(html)
+            .map(|conversion| conversion.markdown)
+    }
+
+    /// Like `try_convert_to_markdown`, but also returns the warnings about
+    /// what was lost on the way, such as elements and styles that have no
+    /// Markdown syntax.
+    pub fn try_convert_with_warnings(&self, html: &str) -> Result<Conversion, ConvertError> {
+        let dom = self.parse_to_dom(html);
+        let mut ctx = ConversionContext::new();
+        let document = self.lower_document(&dom, &mut ctx);
+        if let Some(error) = ctx.take_error() {
+            return Err(error);
+        }
+        let (markdown, _) = self.write_markdown(&document, &mut ctx);
+
+        Ok(Conversion {
+            markdown,
+            warnings: ctx.take_warnings(),
+        })
+    }
+
+    /// Lowers HTML into a Markdown tree, which `serialize` writes out.
+    ///
+    /// Each element is lowered by its handler, following the handler registry
+    /// and the `UnknownElementPolicy`.
+    pub fn convert_to_ast(&self, html: &str) -> Document {
+        let dom = self.parse_to_dom(html);
+        self.lower_document(&dom, &mut ConversionContext::new())
+    }
+
+    /// Writes a Markdown tree out in the style set by the converter's options.
+    pub fn serialize(&self, document: &Document) -> String {
+        self.write_markdown(document, &mut ConversionContext::new())
+            .0
+    }
+
+    fn lower_document(&self, dom: &RcDom, ctx: &mut ConversionContext) -> Document {
+        let nodes = self.lower_node(&dom.document, ctx);
+        Document {
+            children: ast::into_blocks(nodes),
+        }
+    }
+
+    /// Writes the document, along with where its `Block::Source` blocks
+    /// ended up.
+    fn write_markdown(
+        &self,
+        document: &Document,
+        ctx: &mut ConversionContext,
+    ) -> (String, Vec<NodeSpan>) {
+        let mut serializer = serializer::Serializer::new(&self.options, ctx);
+        let markdown = serializer.document(document);
+        let mut spans = serializer.into_spans();
+        let markdown = self.finish_markdown(markdown, ctx, &mut spans);
+        (markdown, spans)
+    }
+
+    /// Appends the collected link definitions and tidies up the lines,
+    /// moving `spans` along with them.
+    fn finish_markdown(
+        &self,
+        mut markdown: String,
+        ctx: &ConversionContext,
+        spans: &mut [NodeSpan],
+    ) -> String {
+        // Reference-style links have their definitions gathered at the end
+        if !ctx.link_definitions().is_empty() {
+            markdown.push_str("\n\n");
+            for definition in ctx.link_definitions() {
+                markdown.push('[');
+                markdown.push_str(definition.label());
+                markdown.push_str("]: ");
+                handlers::write_destination(&mut markdown, definition.url(), definition.title());
+                markdown.push('\n');
+            }
+        }
+
+        let leading = markdown.len() - markdown.trim_start().len();
+        let (mut markdown, lines) = whitespace::clean_lines(markdown.trim());
+        markdown.truncate(markdown.trim_end().len());
+        for span in spans {
+            let map = |position: usize| {
+                lines
+                    .map(position.saturating_sub(leading))
+                    .min(markdown.len())
+            };
+            span.range = map(span.range.start)..map(span.range.end);
+        }
+        if self.options.trailing_newline {
+            markdown.push('\n');
+        }
+        markdown
+    }
+
+    pub fn options(&self) -> &ConverterOptions {
+        &self.options
     }
 
+    /// Parses HTML the way a browser does, which never fails: markup that
+    /// isn't valid is repaired or read as text.
     fn parse_to_dom(&self, html: &str) -> RcDom {
         let opts = ParseOpts {
             tree_builder: TreeBuilderOpts {
@@ -134,127 +416,289 @@ impl MarkdownConverter {
             ..Default::default()
         };
 
-        parse_document(RcDom::default(), opts)
-            .from_utf8()
-            .read_from(&mut html.as_bytes())
-            .unwrap()
+        parse_document(RcDom::default(), opts).one(html)
+    }
+
+    /// Returns false if a node is nested too deeply to be converted, which
+    /// leaves out the node and everything inside it.
+    fn can_descend(&self, ctx: &mut ConversionContext) -> bool {
+        if ctx.depth() >= self.options.max_depth {
+            ctx.set_error(ConvertError::NestingTooDeep {
+                limit: self.options.max_depth,
+            });
+            return false;
+        }
+        true
+    }
+
+    fn warn_stripped(&self, tag_name: &str, ctx: &mut ConversionContext) {
+        if !options::is_document_element(tag_name) {
+            ctx.warn(ConvertWarning::StrippedElement {
+                tag: tag_name.to_string(),
+            });
+        }
     }
 
-    fn traverse_dom(&self, node: &Handle, output: &mut String, depth: usize) {
+    fn handler_for(
+        &self,
+        tag_name: &str,
+        attrs: &[html5ever::Attribute],
+    ) -> Option<&dyn ElementHandler> {
+        self.matched_handlers
+            .iter()
+            .rev()
+            .find(|(matcher, _)| matcher.matches(tag_name, attrs))
+            .map(|(_, handler)| handler.as_ref())
+            .or_else(|| self.handlers.get(tag_name).map(|handler| handler.as_ref()))
+    }
+
+    /// Writes the children of a node as Markdown, for handlers that
+    /// implement `ElementHandler::handle`. Blocks among them are written on
+    /// lines of their own.
+    pub fn walk_children(&self, node: &Handle, output: &mut String, ctx: &mut ConversionContext) {
+        let nodes = self.lower_children(node, ctx);
+        serializer::Serializer::new(&self.options, ctx).nodes(&nodes, output);
+    }
+
+    fn lower_node(&self, node: &Handle, ctx: &mut ConversionContext) -> Vec<Node> {
+        if !self.can_descend(ctx) {
+            return Vec::new();
+        }
+
         match &node.data {
             NodeData::Text { contents } => {
                 let text = contents.borrow();
-                if !text.trim().is_empty() {
-                    output.push_str(&text);
+                let text = if ctx.in_code() || ctx.in_pre() {
+                    text.to_string()
+                } else {
+                    whitespace::collapse(&text)
+                };
+                if text.is_empty() {
+                    return Vec::new();
                 }
+                vec![Node::Inline(Inline::Text(text))]
             }
             NodeData::Element { name, attrs, .. } => {
                 let tag_name = name.local.as_ref();
+                let attrs = attrs.borrow();
 
-                // Use registered handler if available
-                if let Some(handler) = self.handlers.get(tag_name) {
-                    handler.handle(self, node, &attrs.borrow(), output, depth);
+                let path = if ctx.maps_sources() {
+                    source_map::node_path(node, ctx)
                 } else {
-                    // Default behavior for unknown elements
-                    self.walk_children(node, output, depth);
-                }
+                    None
+                };
+                ctx.push_element(name.local.clone(), node.clone(), path);
+                let nodes = if let Some(handler) = self.handler_for(tag_name, &attrs) {
+                    let nodes = handler.lower(self, node, &attrs, ctx);
+                    source_map::mark_source(nodes, ctx)
+                } else if self.options.keeps_html(tag_name) {
+                    let nodes = RawHtmlHandler.lower(self, node, &attrs, ctx);
+                    source_map::mark_source(nodes, ctx)
+                } else {
+                    self.warn_stripped(tag_name, ctx);
+                    self.lower_children(node, ctx)
+                };
+                ctx.pop_element();
+                nodes
             }
-            _ => self.walk_children(node, output, depth),
+            NodeData::Comment { contents } => vec![handlers::html::lower_comment(contents, ctx)],
+            _ => self.lower_children(node, ctx),
         }
     }
 
-    pub fn walk_children(&self, node: &Handle, output: &mut String, depth: usize) {
-        for child in node.children.borrow().iter() {
-            self.traverse_dom(child, output, depth + 1);
-        }
+    /// Lowers the children of a node, for handlers that lower their
+    /// element's content themselves.
+    pub fn lower_children(&self, node: &Handle, ctx: &mut ConversionContext) -> Vec<Node> {
+        node.children
+            .borrow()
+            .iter()
+            .flat_map(|child| self.lower_node(child, ctx))
+            .collect()
+    }
+
+    /// Lowers the children of a node as the content of a block element.
+    pub fn lower_blocks(&self, node: &Handle, ctx: &mut ConversionContext) -> Vec<Block> {
+        ast::into_blocks(self.lower_children(node, ctx))
+    }
+
+    /// Lowers the children of a node as the content of an inline element.
+    pub fn lower_inlines(&self, node: &Handle, ctx: &mut ConversionContext) -> Vec<Inline> {
+        ast::into_inlines(self.lower_children(node, ctx))
     }
 }
 
 struct StyleParser;
 
 impl StyleParser {
+    /// Parses a `style` attribute into its declarations, with the property
+    /// names in lowercase.
     fn parse_styles(style_str: &str) -> HashMap<String, String> {
         let mut styles = HashMap::new();
         for style in style_str.split(';') {
             if let Some((key, value)) = style.split_once(':') {
-                styles.insert(key.trim().to_string(), value.trim().to_string());
+                styles.insert(key.trim().to_ascii_lowercase(), value.trim().to_string());
             }
         }
         styles
     }
 
-    fn get_markdown_style(styles: &HashMap<String, String>) -> Option<(String, String)> {
-        if let Some(weight) = styles.get("font-weight") {
-            if weight == "bold" || weight == "700" {
-                return Some(("**".to_string(), "**".to_string()));
-            }
+    /// Parses the `style` attribute of an element, if it has one.
+    fn from_attributes(attrs: &[html5ever::Attribute]) -> HashMap<String, String> {
+        attrs
+            .iter()
+            .find(|attr| attr.name.local.as_ref() == "style")
+            .map(|attr| Self::parse_styles(&attr.value))
+            .unwrap_or_default()
+    }
+
+    /// Returns a declaration's value in lowercase, without `!important`.
+    fn value(styles: &HashMap<String, String>, property: &str) -> Option<String> {
+        let value = styles.get(property)?.to_ascii_lowercase();
+        Some(value.trim_end_matches("!important").trim().to_string())
+    }
+
+    /// Returns the formatting the declarations stand for, outermost first.
+    fn get_inline_styles(styles: &HashMap<String, String>) -> Vec<InlineStyle> {
+        let decoration = Self::value(styles, "text-decoration-line")
+            .or_else(|| Self::value(styles, "text-decoration"))
+            .unwrap_or_default();
+        let has_decoration = |line: &str| decoration.split_whitespace().any(|word| word == line);
+
+        let highlight = Self::value(styles, "background-color")
+            .or_else(|| Self::value(styles, "background"))
+            .is_some_and(|color| is_highlight_color(&color))
+            || Self::value(styles, "mso-highlight")
+                .is_some_and(|color| !matches!(color.as_str(), "none" | "auto"));
+
+        let mut inline_styles = Vec::new();
+        if highlight {
+            inline_styles.push(InlineStyle::Highlight);
         }
-        if let Some(style) = styles.get("font-style") {
-            if style == "italic" {
-                return Some(("*".to_string(), "*".to_string()));
-            }
+        if has_decoration("line-through") {
+            inline_styles.push(InlineStyle::Strikethrough);
         }
-        if let Some(decoration) = styles.get("text-decoration") {
-            if decoration == "underline" {
-                return Some(("__".to_string(), "__".to_string()));
-            }
+        if Self::value(styles, "font-weight").is_some_and(|weight| is_bold(&weight)) {
+            inline_styles.push(InlineStyle::Strong);
+        }
+        if Self::value(styles, "font-style")
+            .is_some_and(|style| style.starts_with("italic") || style.starts_with("oblique"))
+        {
+            inline_styles.push(InlineStyle::Emphasis);
         }
-        None
+        if has_decoration("underline") {
+            inline_styles.push(InlineStyle::Underline);
+        }
+        inline_styles
     }
-}
 
-struct LinkHandler;
-impl ElementHandler for LinkHandler {
-    fn handle(
-        &self,
-        converter: &MarkdownConverter,
-        node: &Handle,
-        attrs: &[html5ever::Attribute],
-        output: &mut String,
-        depth: usize,
-    ) {
-        output.push('[');
-        converter.walk_children(node, output, depth);
-        output.push(']');
-        output.push('(');
+    /// Returns true if the declarations set the text in a normal weight,
+    /// which cancels the bold of a `<b>` element.
+    fn is_normal_weight(attrs: &[html5ever::Attribute]) -> bool {
+        Self::value(&Self::from_attributes(attrs), "font-weight")
+            .is_some_and(|weight| !is_bold(&weight))
+    }
 
-        if let Some(href) = attrs.iter().find(|attr| attr.name.local.as_ref() == "href") {
-            output.push_str(&href.value);
+    /// Reports the declarations that have no Markdown formatting. Values
+    /// that leave the text as it is aren't worth a warning.
+    fn warn_unknown(styles: &HashMap<String, String>, ctx: &mut ConversionContext) {
+        let mut unknown: Vec<_> = styles
+            .iter()
+            .filter(|(property, _)| {
+                !matches!(
+                    property.as_str(),
+                    "font-weight"
+                        | "font-style"
+                        | "text-decoration"
+                        | "text-decoration-line"
+                        | "background"
+                        | "background-color"
+                        | "mso-highlight"
+                )
+            })
+            .filter(|(_, value)| {
+                !matches!(
+                    value.as_str(),
+                    "" | "normal" | "none" | "inherit" | "initial" | "unset" | "transparent"
+                )
+            })
+            .collect();
+        unknown.sort();
+        for (property, value) in unknown {
+            ctx.warn(ConvertWarning::UnknownStyle {
+                property: property.clone(),
+                value: value.clone(),
+            });
         }
+    }
+}
+
+/// Returns true for `bold`, `bolder` and weights of 600 and up.
+fn is_bold(weight: &str) -> bool {
+    matches!(weight, "bold" | "bolder") || weight.parse::<u32>().is_ok_and(|weight| weight >= 600)
+}
+
+/// Returns true for background colors that mark text, leaving out the
+/// transparent and white backgrounds that editors put on all text.
+fn is_highlight_color(color: &str) -> bool {
+    let color: String = color.chars().filter(|c| !c.is_whitespace()).collect();
+    let invisible = color.starts_with("rgba(") && color.ends_with(",0)");
+    !invisible
+        && !matches!(
+            color.as_str(),
+            "" | "none"
+                | "transparent"
+                | "inherit"
+                | "initial"
+                | "unset"
+                | "white"
+                | "#fff"
+                | "#ffffff"
+                | "rgb(255,255,255)"
+        )
+}
 
-        output.push(')');
+/// Formatting that a `style` attribute stands for.
+enum InlineStyle {
+    Highlight,
+    Strikethrough,
+    Strong,
+    Emphasis,
+    Underline,
+}
+
+impl InlineStyle {
+    fn wrap(&self, children: Vec<Inline>) -> Inline {
+        match self {
+            InlineStyle::Highlight => Inline::Highlight(children),
+            InlineStyle::Strikethrough => Inline::Strikethrough(children),
+            InlineStyle::Strong => Inline::Strong(children),
+            InlineStyle::Emphasis => Inline::Emphasis(children),
+            InlineStyle::Underline => Inline::Underline(children),
+        }
     }
 }
 
 struct SpanHandler;
 impl ElementHandler for SpanHandler {
-    fn handle(
+    fn lower(
         &self,
         converter: &MarkdownConverter,
         node: &Handle,
         attrs: &[html5ever::Attribute],
-        output: &mut String,
-        depth: usize,
-    ) {
-        let mut style_attr = None;
-        for attr in attrs {
-            if attr.name.local.as_ref() == "style" {
-                style_attr = Some(attr.value.as_ref());
-                break;
-            }
+        ctx: &mut ConversionContext,
+    ) -> Vec<Node> {
+        let styles = StyleParser::from_attributes(attrs);
+        StyleParser::warn_unknown(&styles, ctx);
+        let inline_styles = StyleParser::get_inline_styles(&styles);
+        if inline_styles.is_empty() {
+            return converter.lower_children(node, ctx);
         }
 
-        if let Some(style_str) = style_attr {
-            let styles = StyleParser::parse_styles(style_str);
-            if let Some((prefix, suffix)) = StyleParser::get_markdown_style(&styles) {
-                output.push_str(&prefix);
-                converter.walk_children(node, output, depth);
-                output.push_str(&suffix);
-                return;
-            }
+        // Wrap the children from the innermost formatting outwards
+        let mut children = converter.lower_inlines(node, ctx);
+        for inline_style in inline_styles.iter().rev() {
+            children = vec![inline_style.wrap(children)];
         }
-
-        // If no recognized styles, just process children
-        converter.walk_children(node, output, depth);
+        children.into_iter().map(Node::Inline).collect()
     }
 }
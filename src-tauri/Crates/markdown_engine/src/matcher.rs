@@ -0,0 +1,165 @@
+//! Matching of elements to handlers beyond their tag name.
+
+use html5ever::Attribute;
+
+type Predicate = Box<dyn Fn(&str, &[Attribute]) -> bool>;
+
+/// Selects the elements a handler registered with
+/// `MarkdownConverter::register_matched_handler` applies to.
+///
+/// Every condition that is set has to hold for an element to match, e.g.
+/// `ElementMatcher::tag("div").with_class("callout")` matches
+/// `<div class="callout note">` but not `<div class="note">`.
+#[derive(Default)]
+pub struct ElementMatcher {
+    tag: Option<String>,
+    class: Option<String>,
+    attribute: Option<(String, Option<String>)>,
+    predicate: Option<Predicate>,
+}
+
+impl ElementMatcher {
+    /// Matches elements with the given tag name.
+    pub fn tag(tag: &str) -> Self {
+        Self {
+            tag: Some(tag.to_ascii_lowercase()),
+            ..Default::default()
+        }
+    }
+
+    /// Matches elements of any tag, to be narrowed down with the other conditions.
+    pub fn any() -> Self {
+        Self::default()
+    }
+
+    /// Requires the element's `class` attribute to contain `class`.
+    pub fn with_class(mut self, class: &str) -> Self {
+        self.class = Some(class.to_string());
+        self
+    }
+
+    /// Requires the element to have the attribute `name`, with the given value
+    /// if `value` is set.
+    pub fn with_attribute(mut self, name: &str, value: Option<&str>) -> Self {
+        self.attribute = Some((name.to_string(), value.map(str::to_string)));
+        self
+    }
+
+    /// Requires `predicate` to return true for the element's tag name and attributes.
+    pub fn with_predicate<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&str, &[Attribute]) -> bool + 'static,
+    {
+        self.predicate = Some(Box::new(predicate));
+        self
+    }
+
+    /// Returns the tag name this matcher is limited to, if any.
+    pub fn tag_name(&self) -> Option<&str> {
+        self.tag.as_deref()
+    }
+
+    pub(crate) fn matches(&self, tag: &str, attrs: &[Attribute]) -> bool {
+        if self.tag.as_deref().is_some_and(|expected| expected != tag) {
+            return false;
+        }
+
+        if let Some(class) = &self.class {
+            let has_class = attrs.iter().any(|attr| {
+                attr.name.local.as_ref() == "class"
+                    && attr.value.split_whitespace().any(|c| c == class)
+            });
+            if !has_class {
+                return false;
+            }
+        }
+
+        if let Some((name, value)) = &self.attribute {
+            let has_attribute = attrs.iter().any(|attr| {
+                attr.name.local.as_ref() == name
+                    && value
+                        .as_deref()
+                        .is_none_or(|value| attr.value.as_ref() == value)
+            });
+            if !has_attribute {
+                return false;
+            }
+        }
+
+        self.predicate
+            .as_ref()
+            .is_none_or(|predicate| predicate(tag, attrs))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConversionContext, ElementHandler, MarkdownConverter};
+    use markup5ever_rcdom::Handle;
+
+    /// Writes the same text for every element it handles.
+    struct Fixed(&'static str);
+
+    impl ElementHandler for Fixed {
+        fn handle(
+            &self,
+            _converter: &MarkdownConverter,
+            _node: &Handle,
+            _attrs: &[Attribute],
+            output: &mut String,
+            _ctx: &mut ConversionContext,
+        ) {
+            output.push_str(self.0);
+        }
+    }
+
+    #[test]
+    fn replaces_handlers_by_tag_name() {
+        let mut converter = MarkdownConverter::new();
+        converter
+            .register_handler("MARK", Fixed("marked"))
+            .register_handler("widget", Fixed("widget"));
+        assert_eq!(
+            converter.convert_to_markdown("<p><mark>a</mark> <widget>b</widget></p>"),
+            "marked widget"
+        );
+    }
+
+    #[test]
+    fn prefers_the_latest_matched_handler() {
+        let mut converter = MarkdownConverter::new();
+        converter
+            .register_matched_handler(ElementMatcher::tag("span").with_class("x"), Fixed("first"))
+            .register_matched_handler(
+                ElementMatcher::any().with_attribute("data-kind", Some("y")),
+                Fixed("second"),
+            )
+            .register_matched_handler(
+                ElementMatcher::any().with_predicate(|tag, _| tag == "q"),
+                Fixed("quoted"),
+            );
+        assert_eq!(
+            converter.convert_to_markdown(
+                "<p><span class=\"x\">a</span> <span class=\"x\" data-kind=\"y\">b</span> \
+                 <span data-kind=\"z\">c</span> <q>d</q></p>"
+            ),
+            "first second c quoted"
+        );
+    }
+
+    #[test]
+    fn removes_handlers_limited_to_the_tag() {
+        let mut converter = MarkdownConverter::new();
+        converter.remove_handler("EM").remove_handler("div");
+        assert_eq!(converter.convert_to_markdown("<p><em>a</em></p>"), "a");
+        // The callout handler only matches divs, the math handler any element
+        assert_eq!(
+            converter.convert_to_markdown(
+                "<div class=\"callout\" data-callout=\"note\"><p>a</p></div>\
+                 <div class=\"math-display\">x</div>"
+            ),
+            "a\n\n$$x$$"
+        );
+    }
+}
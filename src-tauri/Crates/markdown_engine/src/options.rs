@@ -0,0 +1,269 @@
+//! Output style options for the markdown converter.
+
+use crate::handlers::longest_run;
+
+/// How headings are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeadingStyle {
+    /// `# Heading`
+    Atx,
+    /// `Heading` underlined with `===` or `---`. Only levels 1 and 2 can be
+    /// written this way, deeper headings fall back to ATX.
+    Setext,
+}
+
+/// How underlined text is written, as Markdown has no syntax of its own for it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnderlineStyle {
+    /// `<u>text</u>`
+    Html,
+    /// `++text++`, as used by markdown-it and some other editors.
+    Plus,
+    /// Drop the underline and keep the text.
+    Strip,
+}
+
+/// How superscript and subscript text is written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScriptStyle {
+    /// `<sup>text</sup>` and `<sub>text</sub>`
+    Html,
+    /// `^text^` and `~text~`, as used by Pandoc. Note that pulldown-cmark reads
+    /// `~text~` as strikethrough.
+    Caret,
+}
+
+/// How hard line breaks are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineBreakStyle {
+    /// A backslash at the end of the line.
+    Backslash,
+    /// Two spaces at the end of the line.
+    Spaces,
+}
+
+/// How links are written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkStyle {
+    /// `[text](url "title")`
+    Inline,
+    /// `[text][1]`, with the numbered definitions collected at the end of the
+    /// document.
+    Reference,
+}
+
+/// What happens to elements that have no handler registered for them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnknownElementPolicy {
+    /// Drop the element and write its content.
+    Strip,
+    /// Keep the element as raw HTML.
+    KeepHtml,
+    /// Keep elements with one of the listed tag names as raw HTML and strip all
+    /// others.
+    Allowlist(Vec<String>),
+}
+
+impl UnknownElementPolicy {
+    /// Keeps the HTML that Rhyolite's notes embed on purpose, as Markdown
+    /// has no syntax for it, and strips everything else.
+    pub fn embedded() -> Self {
+        let embedded = ["details", "iframe", "video", "audio", "figure", "picture"];
+        UnknownElementPolicy::Allowlist(embedded.iter().map(|tag| tag.to_string()).collect())
+    }
+}
+
+/// Controls the Markdown syntax written by `MarkdownConverter`.
+///
+/// The defaults match the output of `convert_to_markdown`.
+#[derive(Clone, Debug)]
+pub struct ConverterOptions {
+    /// Marker for unordered list items: `-`, `*` or `+`.
+    pub bullet: char,
+    /// Delimiter for emphasis: `*` or `_`.
+    pub emphasis_delimiter: char,
+    /// Delimiter for strong emphasis, written twice: `*` or `_`.
+    pub strong_delimiter: char,
+    pub heading_style: HeadingStyle,
+    /// Character for code fences: `` ` `` or `~`.
+    pub fence_char: char,
+    /// Length of code fences, at least 3. Fences are made longer when the code
+    /// contains a run of fence characters this long.
+    pub fence_length: usize,
+    pub underline_style: UnderlineStyle,
+    pub script_style: ScriptStyle,
+    pub line_break: LineBreakStyle,
+    /// Links that already carry a reference label are written in reference
+    /// style whatever this is set to.
+    pub link_style: LinkStyle,
+    /// Elements that make up the document, like `<html>` and `<body>`, are
+    /// always stripped.
+    pub unknown_elements: UnknownElementPolicy,
+    /// Column at which paragraphs are wrapped, or `None` to keep each
+    /// paragraph on a single line.
+    pub wrap_width: Option<usize>,
+    /// End the document with a newline.
+    pub trailing_newline: bool,
+    /// Deepest nesting of elements that is converted. Anything nested deeper
+    /// is left out, and `MarkdownConverter::try_convert_to_markdown` fails,
+    /// so hostile input can't exhaust the stack.
+    pub max_depth: usize,
+}
+
+impl Default for ConverterOptions {
+    fn default() -> Self {
+        Self {
+            bullet: '-',
+            emphasis_delimiter: '*',
+            strong_delimiter: '*',
+            heading_style: HeadingStyle::Atx,
+            fence_char: '`',
+            fence_length: 3,
+            underline_style: UnderlineStyle::Html,
+            script_style: ScriptStyle::Html,
+            line_break: LineBreakStyle::Backslash,
+            link_style: LinkStyle::Inline,
+            unknown_elements: UnknownElementPolicy::Strip,
+            wrap_width: None,
+            trailing_newline: false,
+            max_depth: 256,
+        }
+    }
+}
+
+impl ConverterOptions {
+    /// The options Rhyolite saves notes with: the defaults, keeping the HTML
+    /// that notes embed on purpose.
+    pub fn editor() -> Self {
+        Self {
+            unknown_elements: UnknownElementPolicy::embedded(),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the opening and closing syntax for underlined text.
+    pub(crate) fn underline_delimiters(&self) -> (&'static str, &'static str) {
+        match self.underline_style {
+            UnderlineStyle::Html => ("<u>", "</u>"),
+            UnderlineStyle::Plus => ("++", "++"),
+            UnderlineStyle::Strip => ("", ""),
+        }
+    }
+
+    /// Returns the opening and closing syntax for superscript text.
+    pub(crate) fn superscript_delimiters(&self) -> (&'static str, &'static str) {
+        match self.script_style {
+            ScriptStyle::Html => ("<sup>", "</sup>"),
+            ScriptStyle::Caret => ("^", "^"),
+        }
+    }
+
+    /// Returns the opening and closing syntax for subscript text.
+    pub(crate) fn subscript_delimiters(&self) -> (&'static str, &'static str) {
+        match self.script_style {
+            ScriptStyle::Html => ("<sub>", "</sub>"),
+            ScriptStyle::Caret => ("~", "~"),
+        }
+    }
+
+    /// Returns what ends a line with a hard break, including the newline.
+    pub(crate) fn hard_break(&self) -> &'static str {
+        match self.line_break {
+            LineBreakStyle::Backslash => "\\\n",
+            LineBreakStyle::Spaces => "  \n",
+        }
+    }
+
+    /// Returns true if an element without a handler should be kept as raw HTML.
+    pub(crate) fn keeps_html(&self, tag: &str) -> bool {
+        if is_document_element(tag) {
+            return false;
+        }
+        match &self.unknown_elements {
+            UnknownElementPolicy::Strip => false,
+            UnknownElementPolicy::KeepHtml => true,
+            UnknownElementPolicy::Allowlist(tags) => {
+                tags.iter().any(|allowed| allowed.eq_ignore_ascii_case(tag))
+            }
+        }
+    }
+
+    /// Returns the fence for a code block, which is never shorter than three
+    /// characters and longer than any run of the fence character in `code`.
+    /// Tildes are used when a backtick fence wouldn't allow the info string.
+    pub(crate) fn fence(&self, code: &str, info: &str) -> String {
+        let fence_char = if self.fence_char == '`' && info.contains('`') {
+            '~'
+        } else {
+            self.fence_char
+        };
+        let length = self
+            .fence_length
+            .max(3)
+            .max(longest_run(code, fence_char) + 1);
+        fence_char.to_string().repeat(length)
+    }
+}
+
+/// Returns true for the elements that make up the document rather than its
+/// content.
+pub(crate) fn is_document_element(tag: &str) -> bool {
+    matches!(tag, "html" | "head" | "body")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MarkdownConverter;
+
+    fn convert(options: ConverterOptions, html: &str) -> String {
+        MarkdownConverter::with_options(options).convert_to_markdown(html)
+    }
+
+    #[test]
+    fn writes_the_chosen_syntax() {
+        let options = ConverterOptions {
+            bullet: '*',
+            emphasis_delimiter: '_',
+            strong_delimiter: '_',
+            heading_style: HeadingStyle::Setext,
+            fence_char: '~',
+            line_break: LineBreakStyle::Spaces,
+            ..ConverterOptions::default()
+        };
+        assert_eq!(
+            convert(
+                options,
+                "<h1>Title</h1><ul><li><em>a</em> <strong>b</strong></li></ul>\
+                 <pre><code>x</code></pre><p>c<br>d</p>"
+            ),
+            "Title\n=====\n\n* _a_ __b__\n\n~~~\nx\n~~~\n\nc  \nd"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_asterisks_inside_words() {
+        let options = ConverterOptions {
+            emphasis_delimiter: '_',
+            strong_delimiter: '_',
+            ..ConverterOptions::default()
+        };
+        assert_eq!(convert(options.clone(), "<p>a<em>b</em>c</p>"), "a*b*c");
+        assert_eq!(
+            convert(options.clone(), "<p>x<strong>y</strong>z</p>"),
+            "x**y**z"
+        );
+        assert_eq!(convert(options.clone(), "<p>a<em>b</em> c</p>"), "a*b* c");
+        assert_eq!(convert(options.clone(), "<p><em>b</em>c</p>"), "*b*c");
+        assert_eq!(convert(options, "<p>a <em>b</em>, c</p>"), "a _b_, c");
+    }
+
+    #[test]
+    fn ends_with_a_newline_when_asked() {
+        let options = ConverterOptions {
+            trailing_newline: true,
+            ..ConverterOptions::default()
+        };
+        assert_eq!(convert(options, "<p>a</p>"), "a\n");
+    }
+}
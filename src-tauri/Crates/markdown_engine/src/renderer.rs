@@ -0,0 +1,581 @@
+//! Rendering of Markdown to HTML, the read direction of the editor.
+//!
+//! pulldown-cmark parses the document into events, which are passed through
+//! the registered extensions before being written as HTML. The HTML produced
+//! by the built-in extensions is what the handlers of `MarkdownConverter`
+//! write back to Markdown.
+
+use crate::extensions::{
+    CalloutExtension, HighlightExtension, ImageSizeExtension, MathExtension,
+    ReferenceLinkExtension, TagExtension, WikiLinkExtension,
+};
+use crate::slug::{heading_html_text, Slugger};
+use pulldown_cmark::{
+    html, Alignment, BlockQuoteKind, CodeBlockKind, CowStr, Event, LinkType, Options, Parser, Tag,
+    TagEnd,
+};
+use std::ops::Range;
+
+/// An event together with the byte range of the source it was parsed from.
+pub type SourceEvent<'a> = (Event<'a>, Range<usize>);
+
+/// A pass over the events of a whole document, for syntax that spans blocks
+/// or needs to see the structure around it.
+pub trait RenderExtension {
+    /// Rewrites the events. `source` is the Markdown being rendered, which the
+    /// ranges point into.
+    fn transform<'a>(&self, source: &str, events: Vec<SourceEvent<'a>>) -> Vec<SourceEvent<'a>>;
+}
+
+/// Syntax found within the text of a single block, like `[[wikilinks]]`.
+///
+/// The renderer joins the text between other inline elements before searching
+/// it, and leaves code alone. A match only counts if it was written as is in
+/// the source, so escaped syntax stays text.
+pub trait InlineExtension {
+    /// Returns the byte range of the first match in `text`, if there is one.
+    fn find(&self, text: &str) -> Option<Range<usize>>;
+
+    /// Returns the HTML for a match, given the Markdown it was written as.
+    fn render(&self, source: &str) -> String;
+}
+
+/// Renders Markdown to HTML, with Rhyolite's extensions to the syntax.
+pub struct HtmlRenderer {
+    options: Options,
+    extensions: Vec<Box<dyn RenderExtension>>,
+    inline_extensions: Vec<Box<dyn InlineExtension>>,
+    source_positions: bool,
+    first_line: usize,
+}
+
+impl Default for HtmlRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HtmlRenderer {
+    pub fn new() -> Self {
+        Self::with_options(default_options())
+    }
+
+    /// Creates a renderer with the built-in extensions that parses with the
+    /// given pulldown-cmark options.
+    pub fn with_options(options: Options) -> Self {
+        let mut renderer = Self {
+            options,
+            extensions: Vec::new(),
+            inline_extensions: Vec::new(),
+            source_positions: false,
+            first_line: 1,
+        };
+
+        // Block structure first, so the inline extensions see the final text
+        renderer
+            .register_extension(CalloutExtension)
+            .register_extension(ReferenceLinkExtension)
+            .register_extension(MathExtension)
+            .register_extension(HighlightExtension)
+            .register_extension(ImageSizeExtension);
+
+        renderer
+            .register_inline_extension(WikiLinkExtension)
+            .register_inline_extension(TagExtension);
+
+        renderer
+    }
+
+    /// Adds a pass over the events. Passes run in the order they were added.
+    pub fn register_extension<E>(&mut self, extension: E) -> &mut Self
+    where
+        E: RenderExtension + 'static,
+    {
+        self.extensions.push(Box::new(extension));
+        self
+    }
+
+    /// Adds an extension for inline syntax. Where matches of several
+    /// extensions start at the same place, the one added first wins.
+    pub fn register_inline_extension<E>(&mut self, extension: E) -> &mut Self
+    where
+        E: InlineExtension + 'static,
+    {
+        self.inline_extensions.push(Box::new(extension));
+        self
+    }
+
+    pub fn options(&self) -> Options {
+        self.options
+    }
+
+    /// Adds a `data-sourcepos` attribute to block elements, holding the
+    /// first and last line and column of their Markdown, as in `3:1-4:12`.
+    /// Columns count bytes, both starting at 1. Blocks written by extensions,
+    /// such as callouts, and footnote definitions don't get one.
+    pub fn set_source_positions(&mut self, enabled: bool) -> &mut Self {
+        self.source_positions = enabled;
+        self
+    }
+
+    /// Sets the number `data-sourcepos` gives the first line of the Markdown,
+    /// for Markdown that starts further down a file, e.g. below frontmatter.
+    /// Lines are numbered from 1 by default.
+    pub fn set_first_line(&mut self, line: usize) -> &mut Self {
+        self.first_line = line.max(1);
+        self
+    }
+
+    pub fn render(&self, markdown: &str) -> String {
+        let mut events: Vec<SourceEvent> = Parser::new_ext(markdown, self.options)
+            .into_offset_iter()
+            .collect();
+        for extension in &self.extensions {
+            events = extension.transform(markdown, events);
+        }
+        let events = self.render_inline(markdown, events);
+        let mut events = render_heading_ids(events);
+        if self.source_positions {
+            events = render_source_positions(markdown, self.first_line, events);
+        }
+        let events = render_code_info(events);
+
+        let mut html_output = String::with_capacity(markdown.len() * 3 / 2);
+        html::push_html(&mut html_output, events.into_iter().map(|(event, _)| event));
+        html_output
+    }
+
+    /// Runs the inline extensions over each run of text outside of code
+    /// blocks, image alt text and autolinks, which are all written as is.
+    fn render_inline<'a>(
+        &self,
+        source: &str,
+        events: Vec<SourceEvent<'a>>,
+    ) -> Vec<SourceEvent<'a>> {
+        if self.inline_extensions.is_empty() {
+            return events;
+        }
+
+        let mut rendered = Vec::with_capacity(events.len());
+        let mut events = events.into_iter().peekable();
+        let mut in_code_block = false;
+        let mut image_depth = 0;
+        // Whether each of the links the text is in shows its destination
+        let mut links = Vec::new();
+
+        while let Some((event, range)) = events.next() {
+            match &event {
+                Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                Event::End(TagEnd::CodeBlock) => in_code_block = false,
+                Event::Start(Tag::Image { .. }) => image_depth += 1,
+                Event::End(TagEnd::Image) => image_depth -= 1,
+                Event::Start(Tag::Link { link_type, .. }) => {
+                    links.push(matches!(link_type, LinkType::Autolink | LinkType::Email));
+                }
+                Event::End(TagEnd::Link) => {
+                    links.pop();
+                }
+                _ => {}
+            }
+            let Event::Text(text) = event else {
+                rendered.push((event, range));
+                continue;
+            };
+            if in_code_block || image_depth > 0 || links.last() == Some(&true) {
+                rendered.push((Event::Text(text), range));
+                continue;
+            }
+
+            // Join the run of text, remembering where each byte came from in the
+            // source if it was copied verbatim. pulldown-cmark splits text at
+            // brackets, and smart punctuation changes quotes.
+            let mut merged = String::new();
+            let mut offsets = Vec::new();
+            let mut push_text = |text: &str, range: Range<usize>| {
+                let verbatim = source.get(range.clone()) == Some(text);
+                offsets.extend((0..text.len()).map(|i| verbatim.then_some(range.start + i)));
+                merged.push_str(text);
+            };
+            push_text(&text, range.clone());
+            let mut run = range;
+            while let Some((Event::Text(next), next_range)) = events.peek() {
+                let next = next.to_string();
+                run.end = next_range.end;
+                push_text(&next, next_range.clone());
+                events.next();
+            }
+
+            let mut last = 0;
+            let mut position = 0;
+            while let Some((found, extension)) = self.find_inline(&merged[position..]) {
+                let found = position + found.start..position + found.end;
+                match verbatim_source(source, &offsets, &found) {
+                    Some(raw) if extension.find(raw) == Some(0..raw.len()) => {
+                        if found.start > last {
+                            rendered.push((
+                                Event::Text(CowStr::from(merged[last..found.start].to_string())),
+                                run.clone(),
+                            ));
+                        }
+                        let html = extension.render(raw);
+                        rendered.push((Event::InlineHtml(CowStr::from(html)), run.clone()));
+                        last = found.end;
+                        position = found.end;
+                    }
+                    // Try again after the first character of the rejected match
+                    _ => {
+                        position = found.start
+                            + merged[found.start..]
+                                .chars()
+                                .next()
+                                .map_or(1, char::len_utf8);
+                    }
+                }
+                if position >= merged.len() {
+                    break;
+                }
+            }
+            if last < merged.len() {
+                rendered.push((Event::Text(CowStr::from(merged[last..].to_string())), run));
+            }
+        }
+
+        rendered
+    }
+
+    /// Returns the earliest match of any inline extension in the text.
+    fn find_inline(&self, text: &str) -> Option<(Range<usize>, &dyn InlineExtension)> {
+        self.inline_extensions
+            .iter()
+            .filter_map(|extension| {
+                extension
+                    .find(text)
+                    .filter(|found| !found.is_empty())
+                    .map(|found| (found, extension.as_ref()))
+            })
+            .min_by_key(|(found, _)| found.start)
+    }
+}
+
+/// Keeps the whole info string of fenced code blocks, such as
+/// `rust title="main.rs"`, in a `data-info` attribute. pulldown-cmark only
+/// writes the first word of it as the language class.
+///
+/// This runs after all extensions, which rely on the code block events to
+/// leave code alone.
+fn render_code_info(events: Vec<SourceEvent>) -> Vec<SourceEvent> {
+    let mut rendered = Vec::with_capacity(events.len());
+    let mut in_code_block = false;
+
+    for (event, range) in events {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))
+                if info.trim().contains(char::is_whitespace) =>
+            {
+                let html = code_block_start(&info, "");
+                rendered.push((Event::Html(CowStr::from(html)), range));
+                in_code_block = true;
+            }
+            Event::End(TagEnd::CodeBlock) if in_code_block => {
+                rendered.push((Event::Html(CowStr::from("</code></pre>\n")), range));
+                in_code_block = false;
+            }
+            event => rendered.push((event, range)),
+        }
+    }
+
+    rendered
+}
+
+/// Returns the opening tags of a code block, with `attributes` added to the
+/// `<pre>` element. The first word of the info string becomes the language
+/// class, and all of it is kept in `data-info` if there is more.
+fn code_block_start(info: &str, attributes: &str) -> String {
+    let info = info.trim();
+    let language = info.split_whitespace().next().unwrap_or_default();
+    let mut html = format!("<pre{}><code", attributes);
+    if !language.is_empty() {
+        html.push_str(&format!(r#" class="language-{}""#, escape_html(language)));
+    }
+    if info.contains(char::is_whitespace) {
+        html.push_str(&format!(r#" data-info="{}""#, escape_html(info)));
+    }
+    html.push('>');
+    html
+}
+
+/// Gives every heading without an `{#id}` one made from its text, so links
+/// to it keep working when the document is saved again.
+fn render_heading_ids(mut events: Vec<SourceEvent>) -> Vec<SourceEvent> {
+    let mut slugger = Slugger::default();
+    let mut index = 0;
+    while index < events.len() {
+        let Event::Start(Tag::Heading { id, .. }) = &events[index].0 else {
+            index += 1;
+            continue;
+        };
+        if let Some(id) = id {
+            slugger.take(id);
+            index += 1;
+            continue;
+        }
+
+        let mut end = index + 1;
+        while end < events.len() && !matches!(events[end].0, Event::End(TagEnd::Heading(_))) {
+            end += 1;
+        }
+        // The text is taken from the HTML, as the converter reads it back
+        let mut content = String::new();
+        let content_events = events[index + 1..end]
+            .iter()
+            .map(|(event, _)| event.clone());
+        html::push_html(&mut content, content_events);
+        let text = heading_html_text(&content);
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[index].0 {
+            *id = Some(CowStr::from(slugger.slug(&text)));
+        }
+        index = end;
+    }
+    events
+}
+
+/// Writes the opening tags of block elements with a `data-sourcepos`
+/// attribute, in place of pulldown-cmark's own.
+///
+/// Table cells are written here too, since pulldown-cmark takes their
+/// alignment from the start of the table. The head of a table is left to it,
+/// as that tells it to close the cells as `</th>`.
+fn render_source_positions<'a>(
+    source: &str,
+    first_line: usize,
+    events: Vec<SourceEvent<'a>>,
+) -> Vec<SourceEvent<'a>> {
+    let lines = LineIndex::new(source, first_line);
+    let mut rendered = Vec::with_capacity(events.len());
+    let mut alignments = Vec::new();
+    let mut in_head = false;
+    let mut column = 0;
+    // Whether the HTML so far ends a line, as pulldown-cmark starts blocks
+    // on a new line
+    let mut end_newline = true;
+
+    for (event, range) in events {
+        let position = format!(r#" data-sourcepos="{}""#, lines.sourcepos(&range));
+        let (html, on_new_line) = match &event {
+            Event::Start(Tag::Paragraph) => (format!("<p{}>", position), true),
+            Event::Start(Tag::Heading {
+                level,
+                id,
+                classes,
+                attrs,
+            }) => {
+                let mut html = format!("<{}", level);
+                if let Some(id) = id {
+                    html.push_str(&format!(r#" id="{}""#, escape_html(id)));
+                }
+                if !classes.is_empty() {
+                    let classes: Vec<_> = classes.iter().map(|class| escape_html(class)).collect();
+                    html.push_str(&format!(r#" class="{}""#, classes.join(" ")));
+                }
+                for (name, value) in attrs {
+                    let value = value.as_deref().unwrap_or_default();
+                    html.push_str(&format!(
+                        r#" {}="{}""#,
+                        escape_html(name),
+                        escape_html(value)
+                    ));
+                }
+                html.push_str(&position);
+                html.push('>');
+                (html, true)
+            }
+            Event::Start(Tag::BlockQuote(kind)) => {
+                let class = match kind {
+                    None => "",
+                    Some(BlockQuoteKind::Note) => r#" class="markdown-alert-note""#,
+                    Some(BlockQuoteKind::Tip) => r#" class="markdown-alert-tip""#,
+                    Some(BlockQuoteKind::Important) => r#" class="markdown-alert-important""#,
+                    Some(BlockQuoteKind::Warning) => r#" class="markdown-alert-warning""#,
+                    Some(BlockQuoteKind::Caution) => r#" class="markdown-alert-caution""#,
+                };
+                (format!("<blockquote{}{}>\n", class, position), true)
+            }
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let info = match kind {
+                    CodeBlockKind::Fenced(info) => info.as_ref(),
+                    CodeBlockKind::Indented => "",
+                };
+                (code_block_start(info, &position), true)
+            }
+            Event::End(TagEnd::CodeBlock) => ("</code></pre>\n".to_string(), false),
+            Event::Start(Tag::List(Some(1))) => (format!("<ol{}>\n", position), true),
+            Event::Start(Tag::List(Some(start))) => {
+                (format!("<ol start=\"{}\"{}>\n", start, position), true)
+            }
+            Event::Start(Tag::List(None)) => (format!("<ul{}>\n", position), true),
+            Event::Start(Tag::Item) => (format!("<li{}>", position), true),
+            Event::Start(Tag::DefinitionList) => (format!("<dl{}>\n", position), true),
+            Event::Start(Tag::DefinitionListTitle) => (format!("<dt{}>", position), true),
+            Event::Start(Tag::DefinitionListDefinition) => (format!("<dd{}>", position), true),
+            Event::Rule => (format!("<hr{} />\n", position), true),
+            Event::Start(Tag::Table(table_alignments)) => {
+                alignments = table_alignments.clone();
+                (format!("<table{}>", position), false)
+            }
+            Event::Start(Tag::TableRow) => {
+                column = 0;
+                (format!("<tr{}>", position), false)
+            }
+            Event::Start(Tag::TableCell) => {
+                let cell = if in_head { "th" } else { "td" };
+                let style = match alignments.get(column) {
+                    Some(Alignment::Left) => r#" style="text-align: left""#,
+                    Some(Alignment::Center) => r#" style="text-align: center""#,
+                    Some(Alignment::Right) => r#" style="text-align: right""#,
+                    _ => "",
+                };
+                column += 1;
+                (format!("<{}{}{}>", cell, style, position), false)
+            }
+            _ => {
+                match &event {
+                    Event::Start(Tag::TableHead) => {
+                        in_head = true;
+                        column = 0;
+                    }
+                    Event::End(TagEnd::TableHead) => in_head = false,
+                    _ => {}
+                }
+                end_newline = ends_line(&event, end_newline);
+                rendered.push((event, range));
+                continue;
+            }
+        };
+
+        let html = if on_new_line && !end_newline {
+            format!("\n{}", html)
+        } else {
+            html
+        };
+        end_newline = html.ends_with('\n');
+        rendered.push((Event::Html(CowStr::from(html)), range));
+    }
+
+    rendered
+}
+
+/// Returns whether the HTML pulldown-cmark writes for an event leaves the
+/// output at the start of a line, given whether it was before.
+fn ends_line(event: &Event, end_newline: bool) -> bool {
+    match event {
+        Event::Text(text) | Event::Html(text) | Event::InlineHtml(text) if !text.is_empty() => {
+            text.ends_with('\n')
+        }
+        Event::SoftBreak | Event::HardBreak | Event::Rule | Event::TaskListMarker(_) => true,
+        Event::Code(_)
+        | Event::InlineMath(_)
+        | Event::DisplayMath(_)
+        | Event::FootnoteReference(_) => false,
+        Event::Start(Tag::HtmlBlock | Tag::MetadataBlock(_))
+        | Event::End(TagEnd::HtmlBlock | TagEnd::MetadataBlock(_)) => end_newline,
+        Event::Start(_) => false,
+        Event::End(tag) => !matches!(
+            tag,
+            TagEnd::TableCell
+                | TagEnd::Emphasis
+                | TagEnd::Strong
+                | TagEnd::Strikethrough
+                | TagEnd::Link
+                | TagEnd::Image
+        ),
+        _ => end_newline,
+    }
+}
+
+/// Start of every line of a document, for turning byte offsets into lines
+/// and columns.
+struct LineIndex<'a> {
+    source: &'a str,
+    starts: Vec<usize>,
+    first_line: usize,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(source: &'a str, first_line: usize) -> Self {
+        let starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        Self {
+            source,
+            starts,
+            first_line,
+        }
+    }
+
+    /// Returns the line and column of a byte, the line counting from
+    /// `first_line` and the column from 1.
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let line = self.starts.partition_point(|start| *start <= offset);
+        (
+            line + self.first_line - 1,
+            offset - self.starts[line - 1] + 1,
+        )
+    }
+
+    /// Returns the positions of the first and the last byte of a range, not
+    /// counting the line ending at its end, as `line:column-line:column`.
+    fn sourcepos(&self, range: &Range<usize>) -> String {
+        let text = self.source.get(range.clone()).unwrap_or_default();
+        let end = range.start + text.trim_end_matches(['\n', '\r']).len();
+        let (start_line, start_column) = self.position(range.start);
+        let (end_line, end_column) = self.position(end.saturating_sub(1).max(range.start));
+        format!(
+            "{}:{}-{}:{}",
+            start_line, start_column, end_line, end_column
+        )
+    }
+}
+
+/// Returns the source a match in the joined text was written as, unless the
+/// match starts or ends in text that wasn't copied verbatim, or is escaped.
+fn verbatim_source<'s>(
+    source: &'s str,
+    offsets: &[Option<usize>],
+    found: &Range<usize>,
+) -> Option<&'s str> {
+    let start = offsets[found.start]?;
+    let end = offsets[found.end - 1]?;
+    let raw = source.get(start..=end)?;
+    let escaped = source[..start]
+        .chars()
+        .rev()
+        .take_while(|c| *c == '\\')
+        .count()
+        % 2
+        == 1;
+    (!escaped).then_some(raw)
+}
+
+/// The pulldown-cmark options of the syntax Rhyolite reads and writes.
+///
+/// Smart punctuation is left off, as the curly quotes and dashes it makes
+/// would be saved in place of the straight ones in the file.
+pub(crate) fn default_options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    options.insert(Options::ENABLE_MATH);
+    options.insert(Options::ENABLE_DEFINITION_LIST);
+    options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    options
+}
+
+/// Escapes text for use in HTML content or a double-quoted attribute.
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
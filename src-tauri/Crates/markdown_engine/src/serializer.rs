@@ -0,0 +1,723 @@
+//! Writing of the Markdown tree built by `MarkdownConverter::convert_to_ast`.
+//!
+//! Every block is written on its own and then placed into its parent, which
+//! adds the line prefixes of quotes, list items and definitions. All of the
+//! converter's output goes through here, so each piece of syntax is decided
+//! in one place.
+//!
+//! The ranges that `Block::Source` blocks were written to are recorded as
+//! they are written, relative to the text of the block being written, and
+//! moved along whenever that text is placed into its parent.
+
+use std::borrow::Cow;
+
+use crate::ast::{Block, DefinitionItem, Document, Inline, ListItem, Node};
+use crate::escape;
+use crate::handlers::blocks::write_code_block;
+use crate::handlers::escape_attribute;
+use crate::handlers::formatting::write_code_span;
+use crate::handlers::headers::with_attributes;
+use crate::handlers::images::write_image_element;
+use crate::handlers::links::{autolink, write_link_target};
+use crate::handlers::lists::MAX_LIST_NUMBER;
+use crate::handlers::tables::{format_cell, write_rows, Cell};
+use crate::source_map::{lines_with_offsets, LineMap, NodeSpan};
+use crate::wrap;
+use crate::{ConversionContext, ConverterOptions, HeadingStyle};
+
+pub(crate) struct Serializer<'a> {
+    options: &'a ConverterOptions,
+    // Collects the definitions of reference-style links
+    ctx: &'a mut ConversionContext,
+    // Width of the prefixes in front of the lines being written
+    indent: usize,
+    in_table: bool,
+    // Headings and callout titles have to stay on one line
+    single_line: bool,
+    // The text that comes after the inline being written starts with this
+    next_char: Option<char>,
+    // Whether the last block written was a list, and if it was ordered and
+    // used the other marker
+    last_list: Option<(bool, bool)>,
+    // The list being written follows a list of the same kind
+    other_marker: bool,
+    // Where the `Block::Source` blocks were written, parents first
+    spans: Vec<NodeSpan>,
+    // Spans whose first block hasn't been written yet
+    unstarted: Vec<usize>,
+}
+
+impl<'a> Serializer<'a> {
+    pub(crate) fn new(options: &'a ConverterOptions, ctx: &'a mut ConversionContext) -> Self {
+        Self {
+            options,
+            ctx,
+            indent: 0,
+            in_table: false,
+            single_line: false,
+            next_char: None,
+            last_list: None,
+            other_marker: false,
+            spans: Vec::new(),
+            unstarted: Vec::new(),
+        }
+    }
+
+    pub(crate) fn document(&mut self, document: &Document) -> String {
+        self.blocks(&document.children)
+    }
+
+    /// Returns where the `Block::Source` blocks of the document were written.
+    pub(crate) fn into_spans(self) -> Vec<NodeSpan> {
+        self.spans
+    }
+
+    /// Writes nodes into the Markdown of a handler that doesn't lower its
+    /// element, with blocks on lines of their own.
+    pub(crate) fn nodes(&mut self, nodes: &[Node], output: &mut String) {
+        for node in nodes {
+            match node {
+                Node::Inline(inline) => self.inline(inline, output),
+                Node::Block(block) => {
+                    output.push('\n');
+                    output.push_str(&self.block(block));
+                    output.push('\n');
+                }
+            }
+        }
+    }
+
+    /// Writes blocks separated by blank lines.
+    fn blocks(&mut self, blocks: &[Block]) -> String {
+        let mut output = String::new();
+        self.join_blocks(blocks, |_| "\n\n", &mut output);
+        output
+    }
+
+    /// Writes blocks one after the other, with `separator` giving what goes
+    /// in front of each block but the first. The blocks inside a
+    /// `Block::Source` are written as if they were in its place.
+    ///
+    /// Two lists in a row would be read back as one, so a list that follows
+    /// a list of the same kind is written with the other marker.
+    fn join_blocks(
+        &mut self,
+        blocks: &[Block],
+        separator: fn(&Block) -> &'static str,
+        output: &mut String,
+    ) {
+        for block in blocks {
+            if let Block::Source { path, children } = block {
+                // The span starts with the first of its blocks that is written
+                let index = self.spans.len();
+                self.spans.push(NodeSpan {
+                    path: path.clone(),
+                    range: 0..0,
+                });
+                self.unstarted.push(index);
+                self.join_blocks(children, separator, output);
+                if self.unstarted.last() == Some(&index) {
+                    self.unstarted.pop();
+                    self.spans.truncate(index);
+                } else {
+                    self.spans[index].range.end = output.len();
+                }
+                continue;
+            }
+
+            let other_marker = match (block, self.last_list) {
+                (Block::List { ordered, .. }, Some((last_ordered, last_other))) => {
+                    *ordered == last_ordered && !last_other
+                }
+                _ => false,
+            };
+            self.other_marker = other_marker;
+
+            let mark = self.spans.len();
+            let unstarted = std::mem::take(&mut self.unstarted);
+            let last_list = self.last_list.take();
+            let markdown = self.block(block);
+            self.unstarted = unstarted;
+            self.last_list = last_list;
+            if markdown.is_empty() {
+                self.spans.truncate(mark);
+                continue;
+            }
+            self.last_list = match block {
+                Block::List { ordered, .. } => Some((*ordered, other_marker)),
+                _ => None,
+            };
+            if !output.is_empty() {
+                output.push_str(separator(block));
+            }
+            for index in self.unstarted.drain(..) {
+                self.spans[index].range.start = output.len();
+            }
+            self.move_spans(mark, output.len());
+            output.push_str(&markdown);
+        }
+    }
+
+    /// Moves the spans recorded since `mark` by `offset` bytes, for text that
+    /// is placed after that much other text.
+    fn move_spans(&mut self, mark: usize, offset: usize) {
+        self.map_spans(mark, |position| position + offset);
+    }
+
+    fn map_spans(&mut self, mark: usize, map: impl Fn(usize) -> usize) {
+        for span in &mut self.spans[mark..] {
+            span.range = map(span.range.start)..map(span.range.end);
+        }
+    }
+
+    /// Trims `content`, moving the spans recorded since `mark` with it.
+    fn trim_spans(&mut self, mark: usize, content: &str) -> String {
+        let trimmed = content.trim();
+        let leading = content.len() - content.trim_start().len();
+        self.map_spans(mark, |position| {
+            position.saturating_sub(leading).min(trimmed.len())
+        });
+        trimmed.to_string()
+    }
+
+    fn block(&mut self, block: &Block) -> String {
+        match block {
+            Block::Paragraph(children) => {
+                let content = self.inline_content(children);
+                match self.options.wrap_width {
+                    // Table cells are flattened onto one line, so they are never wrapped
+                    Some(width) if !self.in_table => {
+                        wrap::wrap_paragraph(&content, width.saturating_sub(self.indent))
+                    }
+                    _ => content,
+                }
+            }
+            Block::Heading {
+                level,
+                id,
+                classes,
+                attributes,
+                children,
+            } => {
+                let content = with_attributes(
+                    &self.single_line_content(children),
+                    id.as_deref(),
+                    classes,
+                    attributes,
+                );
+                self.heading(*level, content)
+            }
+            Block::ThematicBreak => "---".to_string(),
+            Block::BlockQuote(children) => {
+                let mark = self.spans.len();
+                self.indent += 2;
+                let content = self.blocks(children);
+                self.indent -= 2;
+                let content = self.trim_spans(mark, &content);
+                let (output, lines) = quote(&content);
+                self.map_spans(mark, |position| lines.map(position));
+                output
+            }
+            Block::Callout {
+                kind,
+                fold,
+                title,
+                children,
+            } => {
+                let title = self.single_line_content(title);
+                let mut output = format!("> [!{}]", kind);
+                if let Some(fold) = fold {
+                    output.push(*fold);
+                }
+                if !title.is_empty() {
+                    output.push(' ');
+                    output.push_str(&title);
+                }
+
+                let mark = self.spans.len();
+                self.indent += 2;
+                let content = self.blocks(children);
+                self.indent -= 2;
+                let content = self.trim_spans(mark, &content);
+                if !content.is_empty() {
+                    output.push('\n');
+                    let (quoted, lines) = quote(&content);
+                    let offset = output.len();
+                    self.map_spans(mark, |position| offset + lines.map(position));
+                    output.push_str(&quoted);
+                }
+                output
+            }
+            Block::List {
+                ordered,
+                start,
+                items,
+            } => {
+                let other_marker = std::mem::take(&mut self.other_marker);
+                let delimiter = if other_marker { ')' } else { '.' };
+                let bullet = match self.options.bullet {
+                    '-' if other_marker => '*',
+                    _ if other_marker => '-',
+                    bullet => bullet,
+                };
+                let mut output = String::new();
+                for (index, item) in items.iter().enumerate() {
+                    let marker = if *ordered {
+                        format!(
+                            "{}{} ",
+                            start.saturating_add(index as u64).min(MAX_LIST_NUMBER),
+                            delimiter
+                        )
+                    } else {
+                        format!("{} ", bullet)
+                    };
+                    if index > 0 {
+                        output.push('\n');
+                    }
+                    let mark = self.spans.len();
+                    let item = self.list_item(&marker, item);
+                    self.move_spans(mark, output.len());
+                    output.push_str(&item);
+                }
+                output
+            }
+            Block::CodeBlock { info, code } => {
+                let mut output = String::new();
+                write_code_block(&mut output, self.options, code, info);
+                output.trim_matches('\n').to_string()
+            }
+            Block::Math(tex) => format!("$${}$$", tex),
+            Block::Table(rows) => {
+                self.in_table = true;
+                let mut paths = Vec::new();
+                let rows: Vec<Vec<Cell>> = rows
+                    .iter()
+                    .map(|row| {
+                        row.iter()
+                            .map(|cell| {
+                                let (path, children) = inline_source(&cell.children);
+                                paths.push(path);
+                                Cell {
+                                    content: format_cell(&self.inline_content(children)),
+                                    alignment: cell.alignment,
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect();
+                self.in_table = false;
+
+                let mut output = String::new();
+                let positions = write_rows(&mut output, &rows);
+
+                // The row of a cell is its parent, written as the whole line.
+                // The output is trimmed of the line break in front of it.
+                let mut paths = paths.into_iter();
+                for row in positions {
+                    let cells: Vec<_> = row.cells.into_iter().zip(paths.by_ref()).collect();
+                    let Some(row_path) = cells.iter().find_map(|(_, path)| path.as_ref()) else {
+                        continue;
+                    };
+                    self.spans.push(NodeSpan {
+                        path: row_path[..row_path.len() - 1].to_vec(),
+                        range: row.line.start - 1..row.line.end - 1,
+                    });
+                    for (range, path) in cells {
+                        if let Some(path) = path {
+                            self.spans.push(NodeSpan {
+                                path: path.clone(),
+                                range: range.start - 1..range.end - 1,
+                            });
+                        }
+                    }
+                }
+                output.trim_matches('\n').to_string()
+            }
+            Block::DefinitionList(items) => {
+                let mut output = String::new();
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        output.push_str("\n\n");
+                    }
+                    let mark = self.spans.len();
+                    let group = self.definition_item(item);
+                    self.move_spans(mark, output.len());
+                    output.push_str(&group);
+                }
+                output
+            }
+            Block::FootnoteDefinition { label, children } => {
+                let mark = self.spans.len();
+                self.indent += 4;
+                let content = self.blocks(children);
+                self.indent -= 4;
+                let content = self.trim_spans(mark, &content);
+                let (output, lines) = indent_lines(&content, &format!("[^{}]: ", label), "    ");
+                self.map_spans(mark, |position| lines.map(position));
+                output
+            }
+            Block::Html(html) => html.clone(),
+            Block::Markdown(markdown) => markdown.clone(),
+            Block::Source { .. } => self.blocks(std::slice::from_ref(block)),
+        }
+    }
+
+    fn heading(&self, level: u8, content: String) -> String {
+        let setext =
+            self.options.heading_style == HeadingStyle::Setext && level <= 2 && !content.is_empty();
+        if setext {
+            let underline = if level == 1 { "=" } else { "-" };
+            let length = content.chars().count().max(3);
+            format!("{}\n{}", content, underline.repeat(length))
+        } else {
+            format!("{} {}", "#".repeat(level as usize), content)
+        }
+    }
+
+    fn list_item(&mut self, marker: &str, item: &ListItem) -> String {
+        let mut content = String::new();
+
+        // Task list items carry their state in front of the content
+        match item.checked {
+            Some(true) => content.push_str("[x] "),
+            Some(false) => content.push_str("[ ] "),
+            None => {}
+        }
+
+        // A nested list starts on the line after its parent's text, while
+        // further paragraphs make the item loose
+        let (path, blocks) = block_source(&item.children);
+        let mark = self.spans.len();
+        self.indent += marker.len();
+        let mut children = String::new();
+        self.join_blocks(
+            blocks,
+            |block| match block {
+                Block::List { .. } => "\n",
+                _ => "\n\n",
+            },
+            &mut children,
+        );
+        self.indent -= marker.len();
+        let children = self.trim_spans(mark, &children);
+        self.move_spans(mark, content.len());
+        content.push_str(&children);
+
+        let (output, lines) = indent_lines(&content, marker, &" ".repeat(marker.len()));
+        self.map_spans(mark, |position| lines.map(position));
+        self.insert_span(mark, path, &output);
+        output
+    }
+
+    fn definition_item(&mut self, item: &DefinitionItem) -> String {
+        let mut output = String::new();
+        let mut lines = 0;
+        let mut next_line = |output: &mut String| {
+            if lines > 0 {
+                output.push('\n');
+            }
+            lines += 1;
+        };
+
+        for term in &item.terms {
+            let (path, inlines) = inline_source(term);
+            let line = self.single_line_content(inlines);
+            next_line(&mut output);
+            if let Some(path) = path {
+                self.spans.push(NodeSpan {
+                    path: path.clone(),
+                    range: output.len()..output.len() + line.len(),
+                });
+            }
+            output.push_str(&line);
+        }
+
+        self.indent += 4;
+        for definition in &item.definitions {
+            let (path, blocks) = block_source(definition);
+            let mark = self.spans.len();
+            let content = self.blocks(blocks);
+            let content = self.trim_spans(mark, &content);
+            let (definition, lines) = indent_lines(&content, ":   ", "    ");
+            self.map_spans(mark, |position| lines.map(position));
+            self.insert_span(mark, path, &definition);
+
+            next_line(&mut output);
+            self.move_spans(mark, output.len());
+            output.push_str(&definition);
+        }
+        self.indent -= 4;
+
+        output
+    }
+
+    /// Records that the element at `path` was written as the whole of
+    /// `output`, in front of the spans of its content from `mark` on.
+    fn insert_span(&mut self, mark: usize, path: Option<&Vec<usize>>, output: &str) {
+        if let Some(path) = path {
+            let span = NodeSpan {
+                path: path.clone(),
+                range: 0..output.len(),
+            };
+            self.spans.insert(mark, span);
+        }
+    }
+
+    /// Writes inline content, without the whitespace at its ends.
+    fn inline_content(&mut self, inlines: &[Inline]) -> String {
+        let mut output = String::new();
+        self.inlines(inlines, &mut output);
+        output.trim_end().to_string()
+    }
+
+    /// Writes inline content that has to fit on a single line.
+    fn single_line_content(&mut self, inlines: &[Inline]) -> String {
+        let single_line = std::mem::replace(&mut self.single_line, true);
+        let content = self.inline_content(inlines);
+        self.single_line = single_line;
+        content.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    fn inlines(&mut self, inlines: &[Inline], output: &mut String) {
+        let after = self.next_char;
+        for (index, inline) in inlines.iter().enumerate() {
+            self.next_char = match inlines.get(index + 1) {
+                Some(next) => leading_char(next),
+                None => after,
+            };
+            self.inline(inline, output);
+        }
+        self.next_char = after;
+    }
+
+    fn inline(&mut self, inline: &Inline, output: &mut String) {
+        match inline {
+            Inline::Text(text) => {
+                // Soft line breaks have to become spaces where lines can't break
+                let text = if self.in_table || self.single_line {
+                    Cow::Owned(text.replace('\n', " "))
+                } else {
+                    Cow::Borrowed(text.as_str())
+                };
+                // A space is only needed between two pieces of inline content
+                let mut text = text.as_ref();
+                if output.is_empty() || output.ends_with([' ', '\n']) {
+                    text = text.trim_start();
+                }
+                if text.is_empty() {
+                    return;
+                }
+
+                let at_line_start = output.is_empty() || output.ends_with('\n');
+                output.push_str(&escape::escape_text(
+                    text,
+                    output.chars().last(),
+                    at_line_start,
+                ));
+            }
+            Inline::Emphasis(children) => {
+                let delimiter = self.options.emphasis_delimiter.to_string();
+                self.delimited(children, &delimiter, &delimiter, output);
+            }
+            Inline::Strong(children) => {
+                let delimiter = self.options.strong_delimiter.to_string().repeat(2);
+                self.delimited(children, &delimiter, &delimiter, output);
+            }
+            Inline::Strikethrough(children) => self.delimited(children, "~~", "~~", output),
+            Inline::Highlight(children) => self.delimited(children, "==", "==", output),
+            Inline::Underline(children) => {
+                let (open, close) = self.options.underline_delimiters();
+                self.delimited(children, open, close, output);
+            }
+            Inline::Superscript(children) => {
+                let (open, close) = self.options.superscript_delimiters();
+                self.delimited(children, open, close, output);
+            }
+            Inline::Subscript(children) => {
+                let (open, close) = self.options.subscript_delimiters();
+                self.delimited(children, open, close, output);
+            }
+            Inline::Keyboard(children) => self.delimited(children, "<kbd>", "</kbd>", output),
+            Inline::Abbreviation { title, children } => {
+                let open = match title {
+                    Some(title) => format!("<abbr title=\"{}\">", escape_attribute(title)),
+                    None => "<abbr>".to_string(),
+                };
+                self.delimited(children, &open, "</abbr>", output);
+            }
+            Inline::Code(code) => write_code_span(output, code),
+            Inline::Math { tex, display } => {
+                let delimiter = if *display { "$$" } else { "$" };
+                output.push_str(delimiter);
+                output.push_str(tex);
+                output.push_str(delimiter);
+            }
+            Inline::Link {
+                url,
+                title,
+                reference,
+                children,
+            } => {
+                if title.is_none() {
+                    if let Some(autolink) = autolink(&Inline::plain_text(children), url) {
+                        output.push('<');
+                        output.push_str(&autolink);
+                        output.push('>');
+                        return;
+                    }
+                }
+
+                let start = output.len();
+                output.push('[');
+                self.inlines(children, output);
+                output.push(']');
+                write_link_target(
+                    self.options.link_style,
+                    output,
+                    start,
+                    url,
+                    title.as_deref(),
+                    reference.as_deref(),
+                    self.ctx,
+                );
+            }
+            Inline::Image {
+                url,
+                alt,
+                title,
+                width,
+                height,
+            } => write_image_element(
+                output,
+                url,
+                Some(alt.clone()).filter(|alt| !alt.trim().is_empty()),
+                title.clone(),
+                width.clone(),
+                height.clone(),
+            ),
+            Inline::WikiLink { target, alias } => {
+                output.push_str("[[");
+                output.push_str(target);
+                if let Some(alias) = alias {
+                    output.push('|');
+                    output.push_str(alias);
+                }
+                output.push_str("]]");
+            }
+            Inline::FootnoteReference(label) => {
+                output.push_str("[^");
+                output.push_str(label);
+                output.push(']');
+            }
+            Inline::Break => {
+                let trimmed = output.trim_end_matches(' ').len();
+                output.truncate(trimmed);
+                if self.in_table {
+                    output.push('\n');
+                } else if self.single_line {
+                    output.push(' ');
+                } else {
+                    output.push_str(self.options.hard_break());
+                }
+            }
+            Inline::Html(html) => output.push_str(html),
+            Inline::Markdown(markdown) => output.push_str(markdown),
+            Inline::Source { children, .. } => self.inlines(children, output),
+        }
+    }
+
+    /// Writes inline content between `open` and `close`.
+    ///
+    /// Whitespace at the edges of the content is moved outside of the
+    /// delimiters, as `**bold **` wouldn't be read as strong emphasis.
+    /// Underscores can't open or close emphasis inside a word, so `*` is
+    /// used there instead. Elements without any content are dropped.
+    fn delimited(&mut self, children: &[Inline], open: &str, close: &str, output: &mut String) {
+        let start = output.len();
+        self.inlines(children, output);
+        let content = output.split_off(start);
+
+        let trimmed = content.trim();
+        if trimmed.is_empty() {
+            output.push_str(&content);
+            return;
+        }
+
+        let leading = &content[..content.len() - content.trim_start().len()];
+        let trailing = &content[content.trim_end().len()..];
+        let before = output.chars().last().filter(|_| leading.is_empty());
+        let after = self.next_char.filter(|_| trailing.is_empty());
+        let intraword = [before, after]
+            .into_iter()
+            .any(|c| c.is_some_and(char::is_alphanumeric));
+        let (open, close) = if intraword && open.starts_with('_') {
+            (open.replace('_', "*"), close.replace('_', "*"))
+        } else {
+            (open.to_string(), close.to_string())
+        };
+        output.push_str(leading);
+        output.push_str(&open);
+        output.push_str(trimmed);
+        output.push_str(&close);
+        output.push_str(trailing);
+    }
+}
+
+/// The first character written for `inline`, if it is written as text.
+fn leading_char(inline: &Inline) -> Option<char> {
+    match inline {
+        Inline::Text(text) | Inline::Markdown(text) => text.chars().next(),
+        Inline::Source { children, .. } => children.first().and_then(leading_char),
+        _ => None,
+    }
+}
+
+/// Splits off the `Block::Source` that stands for a list item or definition.
+fn block_source(blocks: &[Block]) -> (Option<&Vec<usize>>, &[Block]) {
+    match blocks {
+        [Block::Source { path, children }] => (Some(path), children),
+        blocks => (None, blocks),
+    }
+}
+
+/// Splits off the `Inline::Source` that stands for a table cell or term.
+fn inline_source(inlines: &[Inline]) -> (Option<&Vec<usize>>, &[Inline]) {
+    match inlines {
+        [Inline::Source { path, children }] => (Some(path), children),
+        inlines => (None, inlines),
+    }
+}
+
+/// Puts `> ` in front of every line, and `>` alone in front of blank ones.
+fn quote(content: &str) -> (String, LineMap) {
+    let mut output = String::new();
+    let mut lines = LineMap::default();
+    for (index, (offset, line)) in lines_with_offsets(content).enumerate() {
+        if index > 0 {
+            output.push('\n');
+        }
+        // Blank lines, such as those of code blocks, get no trailing space
+        output.push_str(if line.is_empty() { ">" } else { "> " });
+        lines.push(offset, output.len(), line.len());
+        output.push_str(line);
+    }
+    (output, lines)
+}
+
+/// Puts `first` in front of the first line and `rest` in front of every other
+/// line that isn't blank.
+fn indent_lines(content: &str, first: &str, rest: &str) -> (String, LineMap) {
+    let mut output = first.to_string();
+    let mut lines = LineMap::default();
+    for (index, (offset, line)) in lines_with_offsets(content).enumerate() {
+        if index > 0 {
+            output.push('\n');
+            if !line.is_empty() {
+                output.push_str(rest);
+            }
+        }
+        lines.push(offset, output.len(), line.len());
+        output.push_str(line);
+    }
+    (output, lines)
+}
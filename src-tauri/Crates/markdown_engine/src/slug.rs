@@ -0,0 +1,112 @@
+//! Identifiers of headings, made from their text the way GitHub makes them.
+
+use html5ever::parse_document;
+use html5ever::tendril::TendrilSink;
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use std::collections::{HashMap, HashSet};
+
+/// Hands out heading identifiers that are unique within a document.
+///
+/// The renderer and the converter both go through the headings in document
+/// order with one of these, so they agree on the identifier of every heading
+/// that doesn't have one written out.
+#[derive(Default)]
+pub(crate) struct Slugger {
+    taken: HashSet<String>,
+    // The last number added to each slug to tell it apart from an earlier one
+    suffixes: HashMap<String, usize>,
+}
+
+impl Slugger {
+    /// Returns the identifier the next heading with `text` would get.
+    pub(crate) fn peek(&self, text: &str) -> String {
+        self.next(&slug(text)).0
+    }
+
+    /// Returns the identifier of a heading with `text` and takes it.
+    pub(crate) fn slug(&mut self, text: &str) -> String {
+        let base = slug(text);
+        let (id, suffix) = self.next(&base);
+        if suffix > 0 {
+            self.suffixes.insert(base, suffix);
+        }
+        self.taken.insert(id.clone());
+        id
+    }
+
+    /// Takes an identifier that was given explicitly, so no generated one
+    /// ends up the same.
+    pub(crate) fn take(&mut self, id: &str) {
+        self.taken.insert(id.to_string());
+    }
+
+    fn next(&self, base: &str) -> (String, usize) {
+        if !self.taken.contains(base) {
+            return (base.to_string(), 0);
+        }
+        let mut suffix = self.suffixes.get(base).copied().unwrap_or_default();
+        loop {
+            suffix += 1;
+            let id = format!("{}-{}", base, suffix);
+            if !self.taken.contains(&id) {
+                return (id, suffix);
+            }
+        }
+    }
+}
+
+/// Returns the text the identifier of a heading is made from: its text as
+/// it reads in the HTML, with images counting as their alt text and
+/// footnote references left out.
+///
+/// The converter takes it from the heading element and the renderer from
+/// the HTML it writes for the heading's content, so that both make the same
+/// identifier.
+pub(crate) fn heading_text(node: &Handle) -> String {
+    let mut text = String::new();
+    // Walked without recursion, as nothing limits how deep the element goes
+    let mut pending: Vec<Handle> = node.children.borrow().iter().rev().cloned().collect();
+    while let Some(node) = pending.pop() {
+        match &node.data {
+            NodeData::Text { contents } => text.push_str(&contents.borrow()),
+            NodeData::Element { name, attrs, .. } => match name.local.as_ref() {
+                "img" => {
+                    let attrs = attrs.borrow();
+                    let alt = attrs.iter().find(|attr| attr.name.local.as_ref() == "alt");
+                    text.extend(alt.map(|attr| attr.value.as_ref()));
+                }
+                "br" => text.push(' '),
+                "sup" if has_class(&attrs.borrow(), "footnote-reference") => {}
+                _ => pending.extend(node.children.borrow().iter().rev().cloned()),
+            },
+            _ => pending.extend(node.children.borrow().iter().rev().cloned()),
+        }
+    }
+    text
+}
+
+/// Returns the `heading_text` of the HTML written for a heading's content.
+pub(crate) fn heading_html_text(html: &str) -> String {
+    let dom = parse_document(RcDom::default(), Default::default()).one(html);
+    heading_text(&dom.document)
+}
+
+fn has_class(attrs: &[html5ever::Attribute], class: &str) -> bool {
+    attrs.iter().any(|attr| {
+        attr.name.local.as_ref() == "class" && attr.value.split_whitespace().any(|c| c == class)
+    })
+}
+
+/// Lowercases the text, drops punctuation and turns spaces into hyphens.
+/// Runs of whitespace count as a single space.
+fn slug(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || matches!(c, '-' | '_'))
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
@@ -0,0 +1,181 @@
+//! Where the Markdown written for each block element of the HTML ended up.
+//!
+//! While a source map is being made, the blocks that each element lowers to
+//! are kept together in a `Block::Source` holding the element's path. The
+//! serializer records the range it writes every one of them to, and moves
+//! the ranges along as the text is put behind line prefixes and tidied up.
+
+use crate::ast::{Block, Node};
+use crate::ConversionContext;
+use markup5ever_rcdom::{Handle, NodeData};
+use std::ops::Range;
+use std::rc::Rc;
+
+/// The Markdown written for a block element.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeSpan {
+    /// Position of the element below `<body>`, as the index of each of its
+    /// ancestors and then itself among the elements of their parent. Text
+    /// isn't counted, so the path can be followed with `Element.children`.
+    pub path: Vec<usize>,
+    /// Bytes of the output that the element was written as, without the
+    /// line ending after it.
+    pub range: Range<usize>,
+}
+
+/// Where the lines of some Markdown ended up after it was rewritten line by
+/// line, e.g. by putting `> ` in front of each of them.
+#[derive(Debug, Default)]
+pub(crate) struct LineMap {
+    // The start of each line that was kept, before and after, and its length after
+    lines: Vec<(usize, usize, usize)>,
+}
+
+impl LineMap {
+    /// Records that the line starting at `before` now starts at `after` and
+    /// is `len` bytes long. Lines have to be added in order.
+    pub(crate) fn push(&mut self, before: usize, after: usize, len: usize) {
+        self.lines.push((before, after, len));
+    }
+
+    /// Returns where the byte at `offset` ended up. Offsets in text that was
+    /// dropped move to the end of what was kept of the line before.
+    pub(crate) fn map(&self, offset: usize) -> usize {
+        let line = self
+            .lines
+            .partition_point(|(before, _, _)| *before <= offset);
+        match line.checked_sub(1).map(|line| self.lines[line]) {
+            Some((before, after, len)) => after + (offset - before).min(len),
+            None => 0,
+        }
+    }
+}
+
+/// Returns the lines of `text` as `str::lines` does, with the offset each of
+/// them starts at.
+pub(crate) fn lines_with_offsets(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .map(move |line| (line.as_ptr() as usize - text.as_ptr() as usize, line))
+}
+
+/// Keeps the blocks an element was lowered to together in a `Block::Source`,
+/// if a source map is being made. Elements that lowered to inline content
+/// aren't part of the map.
+pub(crate) fn mark_source(nodes: Vec<Node>, ctx: &ConversionContext) -> Vec<Node> {
+    let is_block = !nodes.is_empty() && nodes.iter().all(|node| matches!(node, Node::Block(_)));
+    let path = match ctx.path() {
+        Some(path) if is_block && !path.is_empty() => path.to_vec(),
+        _ => return nodes,
+    };
+
+    let children = nodes
+        .into_iter()
+        .filter_map(|node| match node {
+            Node::Block(block) => Some(block),
+            Node::Inline(_) => None,
+        })
+        .collect();
+    vec![Node::Block(Block::Source { path, children })]
+}
+
+/// Returns the path of an element below `<body>`, which is empty for
+/// `<body>` itself, or `None` for anything outside of it.
+///
+/// Handlers lower the elements of tables and footnotes themselves, so the
+/// path is found from the innermost element being lowered rather than from
+/// the element's parent.
+pub(crate) fn node_path(node: &Handle, ctx: &ConversionContext) -> Option<Vec<usize>> {
+    if is_element(node, "body") {
+        return Some(Vec::new());
+    }
+    let mut path = ctx.path()?.to_vec();
+    for pair in ctx.lineage(node)?.windows(2) {
+        let index = pair[0]
+            .children
+            .borrow()
+            .iter()
+            .filter(|child| matches!(child.data, NodeData::Element { .. }))
+            .position(|child| Rc::ptr_eq(child, &pair[1]))?;
+        path.push(index);
+    }
+    Some(path)
+}
+
+fn is_element(node: &Handle, tag: &str) -> bool {
+    matches!(&node.data, NodeData::Element { name, .. } if name.local.as_ref() == tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MarkdownConverter;
+
+    /// Returns the path of each span with the Markdown it covers.
+    fn spans(html: &str) -> Vec<(Vec<usize>, String)> {
+        let converter = MarkdownConverter::new();
+        let (markdown, spans) = converter.convert_with_source_map(html);
+        assert_eq!(markdown, converter.convert_to_markdown(html));
+        spans
+            .into_iter()
+            .map(|span| (span.path, markdown[span.range].to_string()))
+            .collect()
+    }
+
+    fn span(path: &[usize], markdown: &str) -> (Vec<usize>, String) {
+        (path.to_vec(), markdown.to_string())
+    }
+
+    #[test]
+    fn maps_nested_blocks_behind_their_prefixes() {
+        assert_eq!(
+            spans(
+                "<h1>Title</h1><blockquote><p>Quoted</p>\
+                 <ul><li>a</li><li><p>b</p><p>c</p></li></ul></blockquote>"
+            ),
+            vec![
+                span(&[0], "# Title"),
+                span(&[1], "> Quoted\n>\n> - a\n> - b\n>\n>   c"),
+                span(&[1, 0], "Quoted"),
+                span(&[1, 1], "- a\n> - b\n>\n>   c"),
+                span(&[1, 1, 0], "- a"),
+                span(&[1, 1, 1], "- b\n>\n>   c"),
+                span(&[1, 1, 1, 0], "b"),
+                span(&[1, 1, 1, 1], "c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn maps_elements_lowered_by_their_handlers() {
+        assert_eq!(
+            spans(
+                "<table><thead><tr><th>A</th></tr></thead><tbody><tr><td>1</td></tr></tbody></table>\
+                 <div class=\"footnote-definition\" id=\"1\"><sup class=\"footnote-definition-label\">1</sup>\
+                 <p>Source.</p></div><dl><dt>Term</dt><dd>Meaning</dd></dl>"
+            ),
+            vec![
+                span(&[0], "| A   |\n| --- |\n| 1   |"),
+                span(&[0, 0, 0], "| A   |"),
+                span(&[0, 0, 0, 0], "A"),
+                span(&[0, 1, 0], "| 1   |"),
+                span(&[0, 1, 0, 0], "1"),
+                span(&[1], "[^1]: Source."),
+                span(&[1, 1], "Source."),
+                span(&[2], "Term\n:   Meaning"),
+                span(&[2, 0], "Term"),
+                span(&[2, 1], ":   Meaning"),
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_offsets_with_their_lines() {
+        let mut lines = LineMap::default();
+        lines.push(0, 2, 3);
+        lines.push(4, 8, 1);
+        assert_eq!(lines.map(1), 3);
+        // Dropped text moves to the end of the line
+        assert_eq!(lines.map(3), 5);
+        assert_eq!(lines.map(4), 8);
+    }
+}
@@ -0,0 +1,143 @@
+//! Round trips of Markdown through the renderer and back through the
+//! converter, which is what happens to a note every time it is opened and
+//! saved. Markdown written the way the converter writes it has to come back
+//! unchanged.
+
+use crate::{convert_to_markdown, HtmlRenderer};
+
+#[track_caller]
+fn assert_round_trip(markdown: &str) {
+    let html = HtmlRenderer::new().render(markdown);
+    assert_eq!(
+        convert_to_markdown(&html),
+        markdown,
+        "rendered as:\n{}",
+        html
+    );
+}
+
+/// Saves the note as many times as given, checking it stays the same.
+#[track_caller]
+fn assert_stable(markdown: &str, saves: usize) {
+    let mut saved = markdown.to_string();
+    for save in 1..=saves {
+        saved = convert_to_markdown(&HtmlRenderer::new().render(&saved));
+        assert_eq!(saved, markdown, "changed on save {}", save);
+    }
+}
+
+#[test]
+fn escaping() {
+    assert_round_trip(r"Not \*emphasis\*, not \_this\_ and not \`code\`");
+    assert_round_trip(r"\# Not a heading");
+    assert_round_trip(r"1\. Not a list");
+    assert_round_trip(r"\- Not a list either");
+    assert_round_trip(r"\[Not a link\] and a \[bracket\]");
+    assert_round_trip(r"Brackets in [a \] link](https://example.com)");
+    assert_round_trip(r#"She said "hi" and it's fine"#);
+    assert_round_trip(r"\<not a tag> and a backslash \\");
+    assert_round_trip(r"Not =\=highlighted=\= and not \$math\$");
+}
+
+#[test]
+fn inline_formatting() {
+    assert_round_trip("*Emphasis*, **strong**, ~~struck~~ and `code`");
+    assert_round_trip("==Highlighted== text");
+    assert_round_trip("[A link](https://example.com \"Title\") and <https://example.com>");
+    assert_round_trip("![An image](image.png)");
+    assert_round_trip("![A sized image|300](image.png)");
+    assert_round_trip("![A sized image|300x200](image.png \"Title\")");
+    assert_round_trip("![Pasted](<Pasted image 2024.png>) and [a note](Grüße.md)");
+    assert_round_trip("Line one\\\nline two");
+}
+
+#[test]
+fn soft_line_breaks() {
+    assert_round_trip("A hand\nwrapped paragraph");
+    assert_round_trip("> [!NOTE]\n> A wrapped\n> callout body");
+    assert_round_trip("- An item\n  wrapped by hand");
+}
+
+#[test]
+fn lists() {
+    assert_round_trip("- One\n- Two\n  - Nested\n  - Nested again\n- Three");
+    assert_round_trip("1. One\n2. Two\n3. Three");
+    assert_round_trip("3. Three\n4. Four");
+    assert_round_trip("- [ ] Open\n- [x] Done");
+    assert_round_trip("1. Ordered\n   - Unordered inside\n2. Ordered again");
+    assert_round_trip("- First paragraph\n\n  Second paragraph\n- Next item");
+    assert_round_trip("- One list\n\n* Another list\n\n- And a third");
+    assert_round_trip("1. One list\n\n1) Another list");
+    assert_round_trip("- Item\n  - Nested list\n  * Another nested list");
+}
+
+#[test]
+fn blocks() {
+    assert_round_trip("# Heading\n\nA paragraph.\n\n---\n\n> A quote\n>\n> > Nested");
+    assert_round_trip("```rust\nfn main() {}\n```");
+    assert_round_trip("```\nplain\n\n\nwith blank lines\n```");
+    assert_round_trip("- ```py\n  a = 1  \n\n\n  b = 2\n  ```");
+    assert_round_trip("> ```\n> a\n>\n>\n> b\n> ```");
+}
+
+#[test]
+fn tables() {
+    assert_round_trip("| A   | B   |\n| --- | --- |\n| 1   | 2   |");
+    assert_round_trip(
+        "| Left | Center | Right |\n| :--- | :----: | ----: |\n| a    |   b    |     c |",
+    );
+    assert_round_trip("| Pipe   | Code |\n| ------ | ---- |\n| a \\| b | `x`  |");
+}
+
+#[test]
+fn footnotes() {
+    assert_round_trip("A claim[^1].\n\n[^1]: The source.");
+    assert_round_trip("Named[^note].\n\n[^note]: First paragraph.\n\n    Second paragraph.");
+}
+
+#[test]
+fn callouts() {
+    assert_round_trip("> [!NOTE]\n> A note.");
+    assert_round_trip("> [!WARNING] Custom title\n> Body text.");
+    assert_round_trip("> [!TIP]- Folded\n> Hidden until opened.");
+    assert_round_trip("> [!note]\n> Written in lowercase.");
+    assert_round_trip("> [!Tip] Tip\n> A title that is the type.");
+    assert_round_trip("> [!NOTE] Note\n> The default title, written out.");
+}
+
+#[test]
+fn math() {
+    assert_round_trip("Inline $x^2$ math");
+    assert_round_trip("$$\\sum_{i=0}^n i$$");
+}
+
+#[test]
+fn wikilinks_and_tags() {
+    assert_round_trip("[[Note]], [[Note|alias]] and [[Note#Heading]]");
+    assert_round_trip("Tagged #project and #area/work");
+}
+
+#[test]
+fn wikilinks_and_tags_stay_out_of_alt_text_and_autolinks() {
+    assert_stable("![My #project shot](a.png)", 3);
+    assert_stable("![see [[Note]] here](a.png)", 3);
+    assert_stable("<https://example.com/page#section>", 3);
+    assert_stable(r"![An \] unbalanced bracket](a.png)", 3);
+}
+
+#[test]
+fn heading_ids() {
+    assert_round_trip("# Title\n\n# Title");
+    assert_round_trip("## Custom {#custom-id}");
+    assert_round_trip("## Styled {.wide data-level=2}");
+    assert_round_trip("# Title[^1]\n\n[^1]: A footnote in a heading.");
+    assert_round_trip("# With ![icon](icon.png) image");
+    assert_round_trip(r"# Ends with a brace \}");
+}
+
+#[test]
+fn html_comments() {
+    assert_round_trip("A <!-- note --> in the text");
+    assert_round_trip("First\n\n<!-- between blocks -->\n\nSecond");
+    assert_round_trip("> <!-- in a quote -->");
+}
@@ -0,0 +1,161 @@
+//! HTML whitespace handling for text content and the written Markdown.
+
+use crate::source_map::{lines_with_offsets, LineMap};
+
+/// Collapses every run of HTML whitespace into a single space, as a browser
+/// does when it lays out inline content. A run with a line break in it
+/// becomes a single line break instead, which lays out the same, so that
+/// soft line breaks stay where they were. Non-breaking spaces are kept.
+pub(crate) fn collapse(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+    for c in text.chars() {
+        if matches!(c, ' ' | '\t' | '\n' | '\r' | '\x0C') {
+            if !in_whitespace {
+                collapsed.push(' ');
+            }
+            if c == '\n' {
+                collapsed.pop();
+                collapsed.push('\n');
+            }
+            in_whitespace = true;
+        } else {
+            collapsed.push(c);
+            in_whitespace = false;
+        }
+    }
+    collapsed
+}
+
+/// Removes trailing whitespace from every line and collapses runs of blank
+/// lines into one, leaving the content of fenced code blocks untouched.
+///
+/// Two trailing spaces are kept where they make a hard line break, that is
+/// when the line is followed by more text. Also returns where each line
+/// ended up.
+pub(crate) fn clean_lines(markdown: &str) -> (String, LineMap) {
+    let mut cleaned = String::with_capacity(markdown.len());
+    let mut line_map = LineMap::default();
+    let mut fence: Option<String> = None;
+    let mut previous_blank = false;
+    let mut lines = lines_with_offsets(markdown).peekable();
+
+    while let Some((offset, line)) = lines.next() {
+        // Fences may be nested in quotes or list items, which indent the
+        // lines after the first with spaces
+        let unprefixed = line.trim_start_matches([' ', '>']);
+
+        if let Some(open) = &fence {
+            let closing = unprefixed.trim_end();
+            if closing.len() >= open.len() && closing.chars().all(|c| open.starts_with(c)) {
+                fence = None;
+            }
+            line_map.push(offset, cleaned.len(), line.len());
+            cleaned.push_str(line);
+            cleaned.push('\n');
+            continue;
+        }
+
+        let hard_break = line.ends_with("  ")
+            && lines
+                .peek()
+                .is_some_and(|(_, next)| !next.trim_start_matches([' ', '>']).is_empty());
+        let line = line.trim_end();
+        if let Some(marker) = fence_marker(container_content(line)) {
+            fence = Some(marker);
+        }
+
+        // Quote markers on their own still count as blank lines
+        let is_blank = line.trim_start_matches([' ', '>']).is_empty();
+        if is_blank && previous_blank && line.is_empty() {
+            continue;
+        }
+        previous_blank = is_blank;
+
+        let start = cleaned.len();
+        cleaned.push_str(line);
+        if hard_break && !is_blank && fence.is_none() {
+            cleaned.push_str("  ");
+        }
+        line_map.push(offset, start, cleaned.len() - start);
+        cleaned.push('\n');
+    }
+
+    (cleaned, line_map)
+}
+
+/// Returns what is left of a line after the quote markers and list item
+/// markers it starts with, such as `> - ` or `1. `.
+fn container_content(line: &str) -> &str {
+    let mut rest = line;
+    loop {
+        let trimmed = rest.trim_start_matches(' ');
+        let content = match trimmed.strip_prefix('>') {
+            Some(content) => content,
+            None => match list_marker_len(trimmed) {
+                Some(len) => &trimmed[len..],
+                None => return trimmed,
+            },
+        };
+        rest = content;
+    }
+}
+
+/// Returns the length of the list item marker a line starts with: a bullet
+/// or a number of up to nine digits followed by `.` or `)`, and then a space.
+fn list_marker_len(line: &str) -> Option<usize> {
+    let digits = line.bytes().take_while(u8::is_ascii_digit).count();
+    let marker = match line.as_bytes().get(digits)? {
+        b'-' | b'*' | b'+' if digits == 0 => 1,
+        b'.' | b')' if (1..=9).contains(&digits) => digits + 1,
+        _ => return None,
+    };
+    (line.as_bytes().get(marker) == Some(&b' ')).then_some(marker + 1)
+}
+
+/// Returns the fence that opens a code block on this line, if it is one.
+fn fence_marker(line: &str) -> Option<String> {
+    let fence_char = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = line.chars().take_while(|c| *c == fence_char).count();
+    (length >= 3).then(|| fence_char.to_string().repeat(length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clean(markdown: &str) -> String {
+        clean_lines(markdown).0
+    }
+
+    #[test]
+    fn collapses_whitespace_keeping_line_breaks() {
+        assert_eq!(collapse("a  \t b"), "a b");
+        assert_eq!(collapse("a \n  b\n\nc"), "a\nb\nc");
+        assert_eq!(collapse("a\r\nb"), "a\nb");
+        assert_eq!(collapse("a\u{a0} b"), "a\u{a0} b");
+    }
+
+    #[test]
+    fn trims_lines_and_collapses_blank_lines() {
+        assert_eq!(clean("a  \n\n\n\nb   "), "a\n\nb\n");
+        assert_eq!(clean("hard  \nbreak"), "hard  \nbreak\n");
+    }
+
+    #[test]
+    fn leaves_fenced_code_alone() {
+        let code = "```\na = 1  \n\n\n\nb = 2\n```";
+        assert_eq!(clean(code), format!("{}\n", code));
+    }
+
+    #[test]
+    fn finds_fences_after_quote_and_list_markers() {
+        for code in [
+            "- ```py\n  a = 1  \n\n\n  b = 2\n  ```",
+            "12. ~~~\n    a = 1  \n\n\n    ~~~",
+            "> - ```\n>   a  \n>\n>\n>   ```",
+        ] {
+            assert_eq!(clean(code), format!("{}\n", code));
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Line wrapping for paragraphs, used when `ConverterOptions::wrap_width` is set.
+
+/// Wraps each line of a paragraph at `width` columns.
+///
+/// Lines are only broken at spaces outside of code spans, math, wikilinks and HTML tags, and
+/// never in front of a word that would start a list item, heading or quote on the new line.
+pub(crate) fn wrap_paragraph(text: &str, width: usize) -> String {
+    let mut wrapped = String::with_capacity(text.len());
+
+    for (index, line) in text.split('\n').enumerate() {
+        if index > 0 {
+            wrapped.push('\n');
+        }
+
+        // Two trailing spaces are a hard line break and have to stay in place
+        let content = line.trim_end_matches(' ');
+        let trailing = &line[content.len()..];
+
+        let mut column = 0;
+        let mut in_code = false;
+        let mut in_math = false;
+        let mut in_tag = false;
+        let mut in_wikilink = false;
+        let mut unit = String::new();
+        for word in content.split(' ') {
+            if !unit.is_empty() {
+                unit.push(' ');
+            }
+            unit.push_str(word);
+
+            // Words inside a code span, math, a wikilink or an HTML tag are kept together
+            let mut escaped = false;
+            let mut chars = word.chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '`' if !escaped && !in_math && !in_tag => in_code = !in_code,
+                    '$' if !escaped && !in_code && !in_tag => in_math = !in_math,
+                    '<' if !escaped && !in_code && !in_math => {
+                        in_tag = chars
+                            .peek()
+                            .is_some_and(|c| c.is_ascii_alphabetic() || *c == '/');
+                    }
+                    '>' if in_tag => in_tag = false,
+                    '[' if !escaped
+                        && !in_code
+                        && !in_math
+                        && !in_tag
+                        && chars.peek() == Some(&'[') =>
+                    {
+                        chars.next();
+                        in_wikilink = true;
+                    }
+                    ']' if in_wikilink && chars.peek() == Some(&']') => {
+                        chars.next();
+                        in_wikilink = false;
+                    }
+                    _ => {}
+                }
+                escaped = c == '\\' && !escaped;
+            }
+            if in_code || in_math || in_tag || in_wikilink {
+                continue;
+            }
+
+            push_unit(&mut wrapped, &mut column, &unit, width);
+            unit.clear();
+        }
+        if !unit.is_empty() {
+            push_unit(&mut wrapped, &mut column, &unit, width);
+        }
+        wrapped.push_str(trailing);
+    }
+
+    wrapped
+}
+
+fn push_unit(wrapped: &mut String, column: &mut usize, unit: &str, width: usize) {
+    let length = unit.chars().count();
+    if *column > 0 {
+        if *column + 1 + length > width && !starts_block(unit) {
+            wrapped.push('\n');
+            *column = 0;
+        } else {
+            wrapped.push(' ');
+            *column += 1;
+        }
+    }
+    wrapped.push_str(unit);
+    *column += length;
+}
+
+/// Returns true if a line starting with `word` would be read as a block marker.
+fn starts_block(word: &str) -> bool {
+    let digits = word.chars().take_while(char::is_ascii_digit).count();
+    matches!(word, "-" | "+" | "*" | ">" | "=" | ":")
+        || word.starts_with('#')
+        || word.starts_with('>')
+        || word.chars().all(|c| c == '-' || c == '=')
+        || (digits > 0 && matches!(&word[digits..], "." | ")"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_at_spaces() {
+        assert_eq!(
+            wrap_paragraph("one two three four", 9),
+            "one two\nthree\nfour"
+        );
+        assert_eq!(wrap_paragraph("a hard  \nbreak", 80), "a hard  \nbreak");
+    }
+
+    #[test]
+    fn keeps_code_math_tags_and_wikilinks_together() {
+        assert_eq!(wrap_paragraph("see `a b c` now", 5), "see\n`a b c`\nnow");
+        assert_eq!(wrap_paragraph("see $a + b$ now", 5), "see\n$a + b$\nnow");
+        assert_eq!(
+            wrap_paragraph("see <span class=\"x\">now", 5),
+            "see\n<span class=\"x\">now"
+        );
+        assert_eq!(
+            wrap_paragraph("see [[My Long Note]] now", 5),
+            "see\n[[My Long Note]]\nnow"
+        );
+        assert_eq!(
+            wrap_paragraph("see [[Note|an alias]] now", 5),
+            "see\n[[Note|an alias]]\nnow"
+        );
+    }
+
+    #[test]
+    fn never_starts_a_line_with_a_block_marker() {
+        assert_eq!(wrap_paragraph("text - more", 5), "text -\nmore");
+        assert_eq!(wrap_paragraph("text 1. more", 5), "text 1.\nmore");
+        assert_eq!(wrap_paragraph("text #tag", 5), "text #tag");
+    }
+}
@@ -0,0 +1,117 @@
+//! This module splits YAML and TOML frontmatter off notes and writes it back.
+
+use markdown_engine::{join_frontmatter, split_frontmatter, FrontmatterBlock}; //markdown_engine module to find and write the frontmatter block
+use serde::{Deserialize, Serialize}; //serde module to send frontmatter to the frontend
+use serde_json::Value; //serde_json Value type to hold the parsed metadata
+
+/// Format of a frontmatter block, told apart by its fences.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontmatterFormat {
+    /// Fenced by `---`, closed by `---` or `...`.
+    Yaml,
+    /// Fenced by `+++`.
+    Toml,
+}
+
+/// Frontmatter block at the start of a note, such as its tags, aliases and date.
+///
+/// The fences, the text between them and the blank lines after the block are
+/// all kept exactly as they are in the file, so saving a note leaves its
+/// frontmatter untouched.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Frontmatter {
+    pub format: FrontmatterFormat,
+    /// The opening fence line.
+    pub fence: String,
+    /// The text between the fences.
+    pub raw: String,
+    /// The closing fence line.
+    pub closing: String,
+    /// The line break after the closing fence and any blank lines after it.
+    pub separator: String,
+    /// The parsed metadata, or None if the block couldn't be parsed.
+    pub metadata: Option<Value>,
+}
+
+/// Splits the frontmatter off the start of a note. Returns the frontmatter, if
+/// there is a complete block, and the rest of the note.
+pub fn split(markdown: &str) -> (Option<Frontmatter>, &str) {
+    let Some((block, body)) = split_frontmatter(markdown) else {
+        return (None, markdown);
+    };
+    let format = if block.is_toml() {
+        FrontmatterFormat::Toml
+    } else {
+        FrontmatterFormat::Yaml
+    };
+    let frontmatter = Frontmatter {
+        format,
+        fence: block.fence.to_string(),
+        raw: block.raw.to_string(),
+        closing: block.closing.to_string(),
+        separator: block.separator.to_string(),
+        metadata: parse(format, block.raw),
+    };
+    (Some(frontmatter), body)
+}
+
+/// Puts the frontmatter back in front of the note's body, as it was in the file.
+pub fn join(frontmatter: &Frontmatter, body: &str) -> String {
+    let block = FrontmatterBlock {
+        fence: &frontmatter.fence,
+        raw: &frontmatter.raw,
+        closing: &frontmatter.closing,
+        separator: &frontmatter.separator,
+    };
+    join_frontmatter(block, body)
+}
+
+fn parse(format: FrontmatterFormat, raw: &str) -> Option<Value> {
+    match format {
+        FrontmatterFormat::Yaml => serde_yaml_ng::from_str(raw).ok(),
+        FrontmatterFormat::Toml => toml::from_str(raw).ok().map(toml_to_json),
+    }
+}
+
+/// Converts TOML metadata to JSON for the frontend. Dates and times become
+/// strings, the way YAML's are read.
+fn toml_to_json(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(string) => Value::String(string),
+        toml::Value::Integer(integer) => Value::from(integer),
+        toml::Value::Float(float) => Value::from(float),
+        toml::Value::Boolean(boolean) => Value::Bool(boolean),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(array) => Value::Array(array.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn reads_toml_dates_as_strings() {
+        let (frontmatter, body) = split("+++\ndate = 2024-01-01\nat = 07:32:00\n+++\n\nBody");
+        assert_eq!(
+            frontmatter.unwrap().metadata,
+            Some(json!({ "date": "2024-01-01", "at": "07:32:00" }))
+        );
+        assert_eq!(body, "Body");
+    }
+
+    #[test]
+    fn writes_frontmatter_back_as_it_was() {
+        let note = "---\r\ntags: [a]\r\n...\r\n\r\nBody";
+        let (frontmatter, body) = split(note);
+        assert_eq!(join(&frontmatter.unwrap(), body), note);
+    }
+}
@@ -8,6 +8,7 @@ use uuid::Uuid; //Uuid module to generate unique ids
 use dirs; //dirs module to get the path of the documents directory
 use sanitize_filename; //sanitize_filename module to sanitize filenames
 
+use crate::editor::frontmatter;
 use crate::editor::markdown_handler;
 
 use crate::{DocumentData, RecentFileInfo, UserData}; //Importing the DocumentData, RecentFileInfo and UserData structs
@@ -122,8 +123,22 @@ pub fn save_user_data() -> Result<(), String> {
 }
 
 /// This function saves the document.
+///
+/// The frontmatter the document was loaded with, if any, is written back in
+/// front of the content exactly as it was.
+///
+/// Returns the path of the file, and the warnings for any content that
+/// couldn't be written as Markdown.
 #[tauri::command]
-pub fn save_document(id: String, title: String, content: String) -> Result<String, String> {
+pub fn save_document(
+    id: String,
+    title: String,
+    content: String,
+    frontmatter: Option<frontmatter::Frontmatter>,
+) -> Result<(String, Vec<String>), String> {
+    // Convert HTML to Markdown first, so nothing changes if it fails
+    let (markdown_content, warnings) = markdown_handler::html_to_markdown(&content)?;
+
     let mut recent_files = RECENT_FILES
         .lock()
         .map_err(|e| format!("Failed to lock RECENT_FILES: {}", e))?;
@@ -138,9 +153,6 @@ pub fn save_document(id: String, title: String, content: String) -> Result<Strin
     // Create a vault directory within documents_dir
     let trove_dir = get_trove_dir("Untitled_Trove");
 
-    // Convert HTML to Markdown
-    let markdown_content = markdown_handler::html_to_markdown(&content);
-
     // Append .md to the title and sanitize it
     let safe_filename = sanitize_filename::sanitize(format!("{}.md", title));
     let file_path = trove_dir.join(&safe_filename);
@@ -157,6 +169,12 @@ pub fn save_document(id: String, title: String, content: String) -> Result<Strin
         .unwrap_or_else(|| String::from("Untitled"));
     let old_path = trove_dir.join(sanitize_filename::sanitize(format!("{}.md", old_title)));
 
+    // Put the frontmatter back in front of the content
+    let markdown_content = match &frontmatter {
+        Some(frontmatter) => frontmatter::join(frontmatter, &markdown_content),
+        None => markdown_content,
+    };
+
     // if the title has changed, delete the old file
     if old_path != file_path && old_path.exists() {
         fs::remove_file(old_path).map_err(|e| format!("Failed to delete old file: {}", e))?;
@@ -164,7 +182,7 @@ pub fn save_document(id: String, title: String, content: String) -> Result<Strin
 
     // Write markdown content directly to file
     match fs::write(&file_path, markdown_content) {
-        Ok(_) => Ok(file_path.to_string_lossy().to_string()),
+        Ok(_) => Ok((file_path.to_string_lossy().to_string(), warnings)),
         Err(e) => Err(format!("Failed to write file: {}", e)),
     }
 }
@@ -219,7 +237,7 @@ pub fn delete_document(id: String) -> Result<Option<DocumentData>, String> {
                     .map_err(|e| format!("Failed to lock CURRENT_OPEN_TAB: {}", e))?;
                 *current_open_tab = next_id.clone();
                 // Get the document content for the next tab
-                get_document_content(next_id.clone(), next_tab.title.clone())?
+                get_document_content(next_id.clone(), next_tab.title.clone(), None)?
             } else {
                 None
             };
@@ -250,8 +268,15 @@ pub fn delete_document(id: String) -> Result<Option<DocumentData>, String> {
 }
 
 /// This function gets the content of the document by its id and title.
+///
+/// With `source_positions`, the lines in the HTML's `data-sourcepos`
+/// attributes are lines of the file, frontmatter included.
 #[tauri::command]
-pub fn get_document_content(id: String, title: String) -> Result<Option<DocumentData>, String> {
+pub fn get_document_content(
+    id: String,
+    title: String,
+    source_positions: Option<bool>,
+) -> Result<Option<DocumentData>, String> {
     // Get the path of the document using title
     let trove_dir = get_trove_dir("Untitled_Trove");
     let file_path = trove_dir.join(format!("{}.md", title));
@@ -264,15 +289,24 @@ pub fn get_document_content(id: String, title: String) -> Result<Option<Document
 
     // Read the file content using the file path
     match fs::read_to_string(&file_path) {
-        // If the file is read successfully, convert the markdown content to HTML
+        // If the file is read successfully, split off the frontmatter and
+        // convert the rest of the markdown content to HTML
         Ok(content) => {
-            let html_output = markdown_handler::markdown_to_html(&content);
+            let (frontmatter, body) = frontmatter::split(&content);
+            // The body starts on the line after the frontmatter
+            let first_line = content[..content.len() - body.len()].matches('\n').count() + 1;
+            let html_output = markdown_handler::markdown_to_html(
+                body,
+                source_positions.unwrap_or(false),
+                first_line,
+            );
 
             // Return the document data as Some(DocumentData)
             Ok(Some(DocumentData {
                 id,
                 title,
                 content: html_output,
+                frontmatter,
             }))
         }
         // If there is an error in reading the file, return the error
@@ -319,7 +353,7 @@ pub fn load_last_open_tabs() -> Result<Vec<DocumentData>, String> {
                         tabs.clear();
                         for tab in user_data.tabs {
                             // Try to load each document by ID
-                            match get_document_content(tab.id.clone(), tab.title.clone()) {
+                            match get_document_content(tab.id.clone(), tab.title.clone(), None) {
                                 Ok(Some(doc)) => {
                                     last_open_files.push(doc);
                                     tabs.insert(tab.id.clone(), tab.clone());
@@ -353,7 +387,7 @@ pub fn load_last_open_tabs() -> Result<Vec<DocumentData>, String> {
                     .unwrap_or_default();
 
                 let id = Uuid::new_v4().to_string();
-                get_document_content(id, title).ok().flatten()
+                get_document_content(id, title, None).ok().flatten()
             })
             .collect(),
         Err(e) => return Err(format!("Failed to read directory: {}", e)),
@@ -1,30 +1,32 @@
-use markdown_engine::convert_to_markdown; //markdown_engine module to convert html to markdown
-use pulldown_cmark::{html, Options, Parser}; //pulldown_cmark module to parse markdown
-use regex::Regex; //regex module to use Regex type
+use markdown_engine::{ConverterOptions, HtmlRenderer, MarkdownConverter}; //markdown_engine module to convert between html and markdown
 
-pub fn html_to_markdown(html: &str) -> String {
+/// Converts the editor's HTML to Markdown, along with a warning for each piece
+/// of content that had to be left out. HTML that can't be converted at all is
+/// an error, so a note is never overwritten with a broken copy.
+pub fn html_to_markdown(html: &str) -> Result<(String, Vec<String>), String> {
     // let re = Regex::new(r"<mark>(.*?)</mark>").unwrap();
     // let new_html = re.replace_all(html, "==$1==").to_string();
     // let markdown = parse_html(&new_html);
     let html_string = html.to_string();
     // markdown.replace(r"\==", "==")
-    convert_to_markdown(&html_string)
-}
 
-pub fn markdown_to_html(markdown: &str) -> String {
-    let re = Regex::new(r"==(.+?)==").unwrap();
-    let processed_markdown = re.replace_all(markdown, r"<mark>$1</mark>").to_string();
-    
-    // Convert markdown to HTML
-    let mut options = Options::empty();
-    options.insert(Options::ENABLE_TABLES);
-    options.insert(Options::ENABLE_FOOTNOTES);
-    options.insert(Options::ENABLE_STRIKETHROUGH);
-    options.insert(Options::ENABLE_TASKLISTS);
-    options.insert(Options::ENABLE_SMART_PUNCTUATION);
+    let conversion = MarkdownConverter::with_options(ConverterOptions::editor())
+        .try_convert_with_warnings(&html_string)
+        .map_err(|e| format!("Failed to convert document to Markdown: {}", e))?;
+    let warnings = conversion
+        .warnings
+        .iter()
+        .map(|warning| warning.to_string())
+        .collect();
+    Ok((conversion.markdown, warnings))
+}
 
-    let parser = Parser::new_ext(&processed_markdown, options);
-    let mut html_output = String::new();
-    html::push_html(&mut html_output, parser);
-    html_output
+/// Renders a note for the editor. With `source_positions`, block elements
+/// carry a `data-sourcepos` attribute with the lines they came from, counting
+/// the first line of `markdown` as `first_line`.
+pub fn markdown_to_html(markdown: &str, source_positions: bool, first_line: usize) -> String {
+    HtmlRenderer::new()
+        .set_source_positions(source_positions)
+        .set_first_line(first_line)
+        .render(markdown)
 }
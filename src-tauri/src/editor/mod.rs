@@ -1,5 +1,6 @@
 //! This module has all the submodules required by the application.
 
+pub mod frontmatter;
 pub mod io;
 pub mod tabs;
 pub mod markdown_handler;
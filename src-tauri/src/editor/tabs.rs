@@ -89,7 +89,7 @@ pub fn new_tab() -> Result<Tab, String> {
 
     // Save changes to userdata.json
     save_user_data()?;
-    let _ = save_document(new_id, title, String::new());
+    let _ = save_document(new_id, title, String::new(), None);
     
     Ok(new_tab)
 }
@@ -6,12 +6,13 @@ use once_cell::sync::Lazy;
 use tauri::WindowEvent;
 mod editor;
 
-///DocumentData struct, datatype that stores id, title and content of the document.
+///DocumentData struct, datatype that stores id, title, content and frontmatter of the document.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct DocumentData {
     id: String,  
     title: String,
     content: String,
+    frontmatter: Option<editor::frontmatter::Frontmatter>,
 }
 
 ///Tab struct, used to store order(index of the tab), id of the document and title of the document.
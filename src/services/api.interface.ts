@@ -1,5 +1,5 @@
 import type { Tab } from "../types/tab";
-import type { Document, RecentFileInfo } from "../types/document";
+import type { Document, Frontmatter, RecentFileInfo } from "../types/document";
 
 export interface IApiServiceProvider {
   addNewDocumentTab(): Promise<Tab>;
@@ -14,11 +14,13 @@ export interface IApiServiceProvider {
     documentId,
     documentTitle,
     documentContent,
+    documentFrontmatter,
   }: {
     documentId: string;
     documentTitle: string;
     documentContent: any;
-  }): Promise<void>;
+    documentFrontmatter?: Frontmatter | null;
+  }): Promise<string[]>;
 
   getLastOpenedTabs(): Promise<Document[]>;
 
@@ -2,7 +2,7 @@ import type { Tab } from "../types/tab";
 import tabsStore from "../stores/tabs.store";
 import { ApiProvider } from "./api.service";
 import TabService from "./tab.service";
-import type { Document } from "../types/document";
+import type { Document, Frontmatter } from "../types/document";
 import { isValidJSON } from "../helpers/common.helper";
 
 const apiProvider = new ApiProvider();
@@ -81,15 +81,18 @@ const saveDocument = async ({
   documentId,
   documentTitle,
   documentContent,
+  documentFrontmatter,
 }: {
   documentId: string;
   documentTitle: string;
   documentContent: any;
-}): Promise<void> => {
-  await apiProvider.saveDocument({
+  documentFrontmatter?: Frontmatter | null;
+}): Promise<string[]> => {
+  return await apiProvider.saveDocument({
     documentId,
     documentTitle,
     documentContent: documentContent || "",
+    documentFrontmatter,
   });
 };
 
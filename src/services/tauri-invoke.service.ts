@@ -1,6 +1,6 @@
 import type { Tab } from "../types/tab";
 import { invoke } from "@tauri-apps/api/core";
-import type { Document, RecentFileInfo } from "../types/document";
+import type { Document, Frontmatter, RecentFileInfo } from "../types/document";
 import type { IApiServiceProvider } from "./api.interface";
 
 export class TauriInvokeServiceProvider implements IApiServiceProvider {
@@ -30,20 +30,24 @@ export class TauriInvokeServiceProvider implements IApiServiceProvider {
     documentId,
     documentTitle,
     documentContent,
+    documentFrontmatter,
   }: {
     documentId: string;
     documentTitle: string;
     documentContent: string;
-  }): Promise<void> {
-    await invoke("save_document", {
+    documentFrontmatter?: Frontmatter | null;
+  }): Promise<string[]> {
+    const [, warnings] = await invoke<[string, string[]]>("save_document", {
       id: documentId,
       title: documentTitle,
       content: documentContent,
+      frontmatter: documentFrontmatter ?? null,
     });
     await invoke("update_tab_title", {
       id: documentId,
       title: documentTitle,
     });
+    return warnings;
   }
 
   async getLastOpenedTabs(): Promise<Document[]> {
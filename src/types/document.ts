@@ -1,7 +1,24 @@
+// Frontmatter block at the start of a note. Everything but the metadata is
+// exactly as it is in the file, and is sent back unchanged when saving.
+export interface Frontmatter {
+  format: "yaml" | "toml";
+  // The opening fence line.
+  fence: string;
+  // The text between the fences.
+  raw: string;
+  // The closing fence line.
+  closing: string;
+  // The line break after the closing fence and any blank lines after it.
+  separator: string;
+  // The parsed metadata, or null if the block couldn't be parsed.
+  metadata: Record<string, any> | null;
+}
+
 export interface Document {
   id: string;
   title: string;
   content: string | any;
+  frontmatter?: Frontmatter | null;
 }
 
 export interface RecentFileInfo {